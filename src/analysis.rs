@@ -0,0 +1,340 @@
+//! Roman numeral analysis of chord segments in a known key.
+//!
+//! Numerals are written relative to the key: uppercase for major chords, lowercase for minor
+//! ones, with accidentals for roots outside the scale (`bVII` in a major key). Chords outside the
+//! key are told apart as secondary dominants (`V7/V`), chords borrowed from the parallel key and
+//! anything else chromatic.
+
+use std::fmt;
+
+use crate::key::{self, Key, KeySegment, Mode};
+use crate::recognizer::Segment;
+use crate::vocabulary::Quality;
+
+// the scales chords are diatonic to, in semitones above the tonic. Minor keys include the
+// leading tone of harmonic minor, so V and vii° are diatonic in them
+const MAJOR_SCALE: [usize; 7] = [0, 2, 4, 5, 7, 9, 11];
+const MINOR_SCALE: [usize; 8] = [0, 2, 3, 5, 7, 8, 10, 11];
+
+// natural minor, which the triads secondary dominants lead to are built on
+const NATURAL_MINOR_SCALE: [usize; 7] = [0, 2, 3, 5, 7, 8, 10];
+
+// numeral of a root by semitones above the tonic, accidentals relative to the scale of the mode
+const MAJOR_NUMERALS: [&str; 12] = ["I", "bII", "II", "bIII", "III", "IV", "#IV", "V", "bVI", "VI", "bVII", "VII"];
+const MINOR_NUMERALS: [&str; 12] = ["I", "bII", "II", "III", "#III", "IV", "#IV", "V", "VI", "#VI", "VII", "#VII"];
+
+/// How a chord relates to the key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    /// Every chord tone belongs to the key.
+    Diatonic,
+    /// A major or dominant 7th chord leading a fifth down to a diatonic chord other than the tonic.
+    SecondaryDominant,
+    /// Taken from the parallel key, like bVII or iv in a major key.
+    Borrowed,
+    /// Outside the key and explained by neither of the above.
+    Chromatic,
+}
+
+impl Role {
+    pub fn name(self) -> &'static str {
+        match self {
+            Role::Diatonic => "diatonic",
+            Role::SecondaryDominant => "secondary dominant",
+            Role::Borrowed => "borrowed",
+            Role::Chromatic => "chromatic",
+        }
+    }
+}
+
+/// A chord as a Roman numeral, e.g. `ii7`, `bVII` or `V7/V`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Numeral {
+    /// Semitones from the tonic up to the root of the chord.
+    pub degree: usize,
+    pub text: String,
+    pub role: Role,
+}
+
+impl fmt::Display for Numeral {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CadenceKind {
+    /// V to I.
+    Authentic,
+    /// IV to I.
+    Plagal,
+    /// A phrase ending on V, before a rest or at the end.
+    Half,
+    /// V to vi, or to VI in a minor key.
+    Deceptive,
+}
+
+impl CadenceKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            CadenceKind::Authentic => "authentic",
+            CadenceKind::Plagal => "plagal",
+            CadenceKind::Half => "half",
+            CadenceKind::Deceptive => "deceptive",
+        }
+    }
+}
+
+/// A cadence arriving on a segment.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cadence {
+    pub kind: CadenceKind,
+    /// Index of the segment the cadence arrives on.
+    pub segment: usize,
+    /// Start of that segment in seconds.
+    pub time: f32,
+}
+
+/// Roman numerals of a run of segments and the cadences found among them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Analysis {
+    /// One per segment, None for no chord.
+    pub numerals: Vec<Option<Numeral>>,
+    pub cadences: Vec<Cadence>,
+}
+
+/// Analyzes `segments` as being in `key`.
+pub fn analyze(segments: &[Segment], key: Key) -> Analysis {
+    analyze_each(segments, &vec![key; segments.len()])
+}
+
+/// Analyzes every segment in the key in effect halfway through it, following modulations.
+/// Segments are left unanalyzed when `keys` is empty.
+pub fn analyze_local(segments: &[Segment], keys: &[KeySegment]) -> Analysis {
+    let segment_keys: Option<Vec<Key>> = segments.iter()
+        .map(|segment| key::key_at(keys, (segment.start + segment.end) / 2.0))
+        .collect();
+
+    match segment_keys {
+        Some(segment_keys) => analyze_each(segments, &segment_keys),
+        None => Analysis { numerals: vec![None; segments.len()], cadences: Vec::new() },
+    }
+}
+
+/// Analyzes every segment in its own key, `keys` holding one per segment.
+pub fn analyze_each(segments: &[Segment], keys: &[Key]) -> Analysis {
+    let numerals = segments.iter().zip(keys)
+        .map(|(segment, &key)| Some(numeral(segment.chord.root?, segment.chord.quality?, key)))
+        .collect();
+
+    Analysis { numerals, cadences: cadences(segments, keys) }
+}
+
+/// The Roman numeral of the chord on `root` with `quality` in `key`, ignoring its bass note.
+pub fn numeral(root: usize, quality: &Quality, key: Key) -> Numeral {
+    let degree = (root + 12 - key.tonic) % 12;
+    let tones: Vec<usize> = quality.intervals.iter().map(|i| (degree + i) % 12).collect();
+    let parallel = key.parallel();
+
+    let target = (degree + 5) % 12;
+    let role = if tones.iter().all(|t| scale(key.mode).contains(t)) {
+        Role::Diatonic
+    } else if matches!(quality.harte, "maj" | "7" | "9") && target != 0 && minor_third_of(key.mode, target).is_some() {
+        Role::SecondaryDominant
+    } else if tones.iter().all(|t| scale(parallel.mode).contains(t)) {
+        Role::Borrowed
+    } else {
+        Role::Chromatic
+    };
+
+    let text = match role {
+        Role::SecondaryDominant => {
+            let target_numeral = degree_numeral(key.mode, target, false);
+            let target_numeral = match minor_third_of(key.mode, target) {
+                Some(true) => target_numeral.to_lowercase(),
+                _ => target_numeral,
+            };
+            format!("V{}/{}", suffix(quality), target_numeral)
+        }
+        _ => {
+            let base = degree_numeral(key.mode, degree, role == Role::Diatonic);
+            let base = if quality.is_minor() { base.to_lowercase() } else { base };
+            format!("{}{}", base, suffix(quality))
+        }
+    };
+
+    Numeral { degree, text, role }
+}
+
+fn scale(mode: Mode) -> &'static [usize] {
+    match mode {
+        Mode::Major => &MAJOR_SCALE,
+        Mode::Minor => &MINOR_SCALE,
+    }
+}
+
+// the numeral of a root, uppercase. The leading tone of a minor key is vii° rather than #vii°
+// when its chord is diatonic
+fn degree_numeral(mode: Mode, degree: usize, diatonic: bool) -> String {
+    match mode {
+        Mode::Major => MAJOR_NUMERALS[degree].to_string(),
+        Mode::Minor if degree == 11 && diatonic => "VII".to_string(),
+        Mode::Minor => MINOR_NUMERALS[degree].to_string(),
+    }
+}
+
+// whether the diatonic triad on `degree` is minor, None when the degree is off the scale or its
+// triad is diminished, which secondary dominants don't lead to
+fn minor_third_of(mode: Mode, degree: usize) -> Option<bool> {
+    let scale = match mode {
+        Mode::Major => &MAJOR_SCALE,
+        Mode::Minor => &NATURAL_MINOR_SCALE,
+    };
+    let i = scale.iter().position(|&d| d == degree)?;
+    let third = (scale[(i + 2) % 7] + 12 - degree) % 12;
+    let fifth = (scale[(i + 4) % 7] + 12 - degree) % 12;
+
+    // the dominant of a minor key is major, with the leading tone of harmonic minor
+    if mode == Mode::Minor && degree == 7 {
+        return Some(false);
+    }
+    (fifth == 7).then_some(third == 3)
+}
+
+// what follows the numeral for each quality, the case of the numeral already telling major
+// from minor
+fn suffix(quality: &Quality) -> &'static str {
+    match quality.harte {
+        "dim" => "°",
+        "aug" => "+",
+        "sus2" => "sus2",
+        "sus4" => "sus4",
+        "7" | "min7" => "7",
+        "maj7" => "maj7",
+        "dim7" => "°7",
+        "hdim7" => "ø7",
+        "maj6" | "min6" => "add6",
+        "9" => "9",
+        "maj(9)" => "add9",
+        "5" => "5",
+        _ => "",
+    }
+}
+
+// cadences between consecutive chords, skipping over no chord and repeats of the same chord
+// with a different bass note. Both chords are taken in the key of the one arrived on
+fn cadences(segments: &[Segment], keys: &[Key]) -> Vec<Cadence> {
+    // (first segment, last segment, root, quality) of each run of the same chord
+    let mut runs: Vec<(usize, usize, usize, &Quality)> = Vec::new();
+    for (i, segment) in segments.iter().enumerate() {
+        let (Some(root), Some(quality)) = (segment.chord.root, segment.chord.quality) else {
+            continue;
+        };
+
+        match runs.last_mut() {
+            Some(last) if last.2 == root && last.3 == quality && last.1 + 1 == i => last.1 = i,
+            _ => runs.push((i, i, root, quality)),
+        }
+    }
+
+    let has_fifth = |quality: &Quality| quality.intervals.contains(&7);
+    let dominant = |degree: usize, quality: &Quality| degree == 7 && matches!(quality.harte, "maj" | "7" | "9");
+    let tonic = |degree: usize, quality: &Quality| degree == 0 && has_fifth(quality);
+    let subdominant = |degree: usize, quality: &Quality| degree == 5 && has_fifth(quality);
+    let submediant = |key: Key, degree: usize, quality: &Quality| match key.mode {
+        Mode::Major => degree == 9 && quality.is_minor() && has_fifth(quality),
+        Mode::Minor => degree == 8 && !quality.is_minor() && has_fifth(quality),
+    };
+
+    let mut cadences = Vec::new();
+    for (i, &(first, last, root, quality)) in runs.iter().enumerate() {
+        let key = keys[first];
+        let degree = (root + 12 - key.tonic) % 12;
+        // degree and quality of the chord before
+        let previous = i.checked_sub(1).map(|p| ((runs[p].2 + 12 - key.tonic) % 12, runs[p].3));
+
+        let kind = match previous {
+            Some((from, from_quality)) if dominant(from, from_quality) && tonic(degree, quality) => {
+                Some(CadenceKind::Authentic)
+            }
+            Some((from, from_quality)) if dominant(from, from_quality) && submediant(key, degree, quality) => {
+                Some(CadenceKind::Deceptive)
+            }
+            Some((from, from_quality)) if subdominant(from, from_quality) && tonic(degree, quality) => {
+                Some(CadenceKind::Plagal)
+            }
+            _ => {
+                // a phrase ends where the chords stop, at the end or on a rest
+                let phrase_end = last + 1 == segments.len() || segments[last + 1].chord.root.is_none();
+                (dominant(degree, quality) && phrase_end).then_some(CadenceKind::Half)
+            }
+        };
+
+        if let Some(kind) = kind {
+            cadences.push(Cadence { kind, segment: first, time: segments[first].start });
+        }
+    }
+
+    cadences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recognizer::ChordResult;
+    use crate::synth::ChordSpec;
+
+    fn segments(labels: &[&str]) -> Vec<Segment> {
+        labels.iter().enumerate()
+            .map(|(i, label)| {
+                let chord = (*label != "N").then(|| ChordSpec::from_harte(label).unwrap());
+                Segment {
+                    start: i as f32,
+                    end: (i + 1) as f32,
+                    chord: ChordResult {
+                        root: chord.as_ref().map(|c| c.root),
+                        quality: chord.as_ref().map(|c| c.quality),
+                        bass: None,
+                        score: 1.0,
+                        probability: 1.0,
+                        candidates: Vec::new(),
+                        chroma: [0.0; 12],
+                    },
+                }
+            })
+            .collect()
+    }
+
+    fn numerals(analysis: &Analysis) -> Vec<String> {
+        analysis.numerals.iter()
+            .map(|numeral| numeral.as_ref().map_or("N".to_string(), |n| n.text.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn analyzes_major_key() {
+        let key = Key { tonic: 0, mode: Mode::Major };
+        let segments = segments(&["C:maj", "A:min", "D:7", "G:maj", "A#:maj", "F:maj", "C:maj", "G:maj", "A:min"]);
+        let analysis = analyze(&segments, key);
+
+        assert_eq!(numerals(&analysis), ["I", "vi", "V7/V", "V", "bVII", "IV", "I", "V", "vi"]);
+        assert_eq!(analysis.numerals[2].as_ref().unwrap().role, Role::SecondaryDominant);
+        assert_eq!(analysis.numerals[4].as_ref().unwrap().role, Role::Borrowed);
+
+        let cadences: Vec<_> = analysis.cadences.iter().map(|c| (c.kind, c.segment)).collect();
+        assert_eq!(cadences, [(CadenceKind::Plagal, 6), (CadenceKind::Deceptive, 8)]);
+    }
+
+    #[test]
+    fn analyzes_minor_key() {
+        let key = Key { tonic: 9, mode: Mode::Minor };
+        let segments = segments(&["A:min", "D:min", "E:7", "A:min", "G#:dim", "F:maj", "E:maj", "N"]);
+        let analysis = analyze(&segments, key);
+
+        assert_eq!(numerals(&analysis), ["i", "iv", "V7", "i", "vii°", "VI", "V", "N"]);
+        assert!(analysis.numerals.iter().flatten().all(|n| n.role == Role::Diatonic));
+
+        let cadences: Vec<_> = analysis.cadences.iter().map(|c| (c.kind, c.segment)).collect();
+        assert_eq!(cadences, [(CadenceKind::Authentic, 3), (CadenceKind::Half, 6)]);
+    }
+}
@@ -0,0 +1,226 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+
+use crate::error::ChordError;
+
+/// Which channels of a multichannel file get analyzed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChannelMode {
+    /// Average all channels into one signal.
+    Downmix,
+    /// Analyze a single channel, 0 being the left one.
+    Channel(usize),
+    /// Analyze every channel on its own.
+    Separate,
+}
+
+/// Reads a WAV file into the signals selected by `mode`, along with the sample rate.
+pub fn open_wav(
+    path: impl AsRef<Path>,
+    mode: ChannelMode,
+) -> Result<(Vec<Vec<f32>>, usize), ChordError> {
+    let file = BufReader::new(File::open(path)?);
+    let reader = hound::WavReader::new(file).map_err(open_error)?;
+
+    let (channels, sr) = read_samples(reader)?;
+    Ok((select_channels(channels, mode)?, sr))
+}
+
+/// Reads WAV data from any reader into the signals selected by `mode`, along with the sample rate.
+pub fn read_wav<R: Read>(reader: R, mode: ChannelMode) -> Result<(Vec<Vec<f32>>, usize), ChordError> {
+    let reader = hound::WavReader::new(reader).map_err(open_error)?;
+
+    let (channels, sr) = read_samples(reader)?;
+    Ok((select_channels(channels, mode)?, sr))
+}
+
+/// Writes mono samples in [-1, 1] to a 16-bit WAV file, clipping anything outside that range.
+pub fn write_wav(path: impl AsRef<Path>, samples: &[f32], sample_rate: usize) -> Result<(), ChordError> {
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: sample_rate as u32,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+
+    let write = || -> Result<(), hound::Error> {
+        let mut writer = hound::WavWriter::create(path, spec)?;
+        for &sample in samples {
+            writer.write_sample((sample.clamp(-1.0, 1.0) * 32767.0).round() as i16)?;
+        }
+        writer.finalize()
+    };
+
+    write().map_err(|e| match e {
+        hound::Error::IoError(e) => ChordError::Write(e),
+        e => ChordError::Write(io::Error::other(e)),
+    })
+}
+
+// hound reports running out of bytes as an io error, but that just means the header is cut short
+fn open_error(e: hound::Error) -> ChordError {
+    match e {
+        hound::Error::IoError(e)
+            if !matches!(e.kind(), io::ErrorKind::Other | io::ErrorKind::UnexpectedEof) =>
+        {
+            ChordError::Io(e)
+        }
+        hound::Error::Unsupported => ChordError::UnsupportedFormat(e.to_string()),
+        e => ChordError::Decode(e.to_string()),
+    }
+}
+
+// reads every sample normalized to [-1, 1], one Vec per channel, along with the sample rate
+fn read_samples<R: Read>(reader: hound::WavReader<R>) -> Result<(Vec<Vec<f32>>, usize), ChordError> {
+    let spec = reader.spec();
+    let sr = spec.sample_rate as usize;
+
+    let samples: Result<Vec<f32>, hound::Error> = match (spec.sample_format, spec.bits_per_sample) {
+        (hound::SampleFormat::Float, 32) => reader.into_samples::<f32>().collect(),
+        (hound::SampleFormat::Int, 8) => reader
+            .into_samples::<i8>()
+            .map(|s| s.map(|s| s as f32 / 128.0))
+            .collect(),
+        (hound::SampleFormat::Int, 16) => reader
+            .into_samples::<i16>()
+            .map(|s| s.map(|s| s as f32 / 32768.0))
+            .collect(),
+        // 24-bit samples come out of hound sign extended into an i32
+        (hound::SampleFormat::Int, bits @ (24 | 32)) => {
+            let scale = (1u64 << (bits - 1)) as f32;
+            reader
+                .into_samples::<i32>()
+                .map(|s| s.map(|s| s as f32 / scale))
+                .collect()
+        }
+        (format, bits) => {
+            return Err(ChordError::UnsupportedFormat(format!("{}-bit {:?}", bits, format)));
+        }
+    };
+
+    // a truncated file shows up here as an io error halfway through the samples
+    let samples = samples.map_err(|e| ChordError::Decode(e.to_string()))?;
+
+    // samples are interleaved frame by frame
+    let n_channels = spec.channels.max(1) as usize;
+    let mut channels = vec![Vec::with_capacity(samples.len() / n_channels); n_channels];
+    for (i, sample) in samples.into_iter().enumerate() {
+        channels[i % n_channels].push(sample);
+    }
+
+    Ok((channels, sr))
+}
+
+// turns the channels of a file into the signals that should be analyzed
+fn select_channels(channels: Vec<Vec<f32>>, mode: ChannelMode) -> Result<Vec<Vec<f32>>, ChordError> {
+    match mode {
+        ChannelMode::Downmix => {
+            let n = channels.iter().map(|c| c.len()).min().unwrap_or(0);
+            let scale = 1.0 / channels.len().max(1) as f32;
+            let mono = (0..n)
+                .map(|i| channels.iter().map(|c| c[i]).sum::<f32>() * scale)
+                .collect();
+            Ok(vec![mono])
+        }
+        ChannelMode::Channel(index) => {
+            let available = channels.len();
+            channels
+                .into_iter()
+                .nth(index)
+                .map(|c| vec![c])
+                .ok_or(ChordError::InvalidChannel { requested: index, available })
+        }
+        ChannelMode::Separate => Ok(channels),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const LEFT: [f32; 4] = [0.5, -0.25, 0.0, -1.0];
+    const RIGHT: [f32; 4] = [-0.5, 0.75, 0.125, 0.25];
+
+    // the two channels above, interleaved into a WAV file in memory
+    fn stereo_wav(sample_format: hound::SampleFormat, bits_per_sample: u16) -> Vec<u8> {
+        let spec = hound::WavSpec { channels: 2, sample_rate: 8000, bits_per_sample, sample_format };
+        let mut bytes = Cursor::new(Vec::new());
+        let mut writer = hound::WavWriter::new(&mut bytes, spec).unwrap();
+
+        let scale = (1i64 << (bits_per_sample - 1)) as f32;
+        for (&left, &right) in LEFT.iter().zip(&RIGHT) {
+            for sample in [left, right] {
+                match (sample_format, bits_per_sample) {
+                    (hound::SampleFormat::Float, _) => writer.write_sample(sample).unwrap(),
+                    (_, 8) => writer.write_sample((sample * scale) as i8).unwrap(),
+                    (_, 16) => writer.write_sample((sample * scale) as i16).unwrap(),
+                    _ => writer.write_sample((sample * scale).clamp(-scale, scale - 1.0) as i32).unwrap(),
+                }
+            }
+        }
+        writer.finalize().unwrap();
+        bytes.into_inner()
+    }
+
+    fn assert_close(decoded: &[f32], expected: &[f32]) {
+        assert_eq!(decoded.len(), expected.len());
+        for (d, e) in decoded.iter().zip(expected) {
+            assert!((d - e).abs() < 1e-6, "{:?} != {:?}", decoded, expected);
+        }
+    }
+
+    #[test]
+    fn decodes_every_sample_format() {
+        let formats = [
+            (hound::SampleFormat::Int, 8),
+            (hound::SampleFormat::Int, 16),
+            (hound::SampleFormat::Int, 24),
+            (hound::SampleFormat::Int, 32),
+            (hound::SampleFormat::Float, 32),
+        ];
+
+        for (format, bits) in formats {
+            let (signals, sr) = read_wav(Cursor::new(stereo_wav(format, bits)), ChannelMode::Separate).unwrap();
+            assert_eq!(sr, 8000);
+            assert_close(&signals[0], &LEFT);
+            assert_close(&signals[1], &RIGHT);
+        }
+    }
+
+    #[test]
+    fn selects_channels() {
+        let wav = || Cursor::new(stereo_wav(hound::SampleFormat::Float, 32));
+
+        let (mix, _) = read_wav(wav(), ChannelMode::Downmix).unwrap();
+        assert_close(&mix[0], &[0.0, 0.25, 0.0625, -0.375]);
+
+        let (left, _) = read_wav(wav(), ChannelMode::Channel(0)).unwrap();
+        assert_close(&left[0], &LEFT);
+        let (right, _) = read_wav(wav(), ChannelMode::Channel(1)).unwrap();
+        assert_close(&right[0], &RIGHT);
+
+        let (separate, _) = read_wav(wav(), ChannelMode::Separate).unwrap();
+        assert_eq!(separate.len(), 2);
+
+        assert!(matches!(
+            read_wav(wav(), ChannelMode::Channel(2)),
+            Err(ChordError::InvalidChannel { requested: 2, available: 2 })
+        ));
+    }
+
+    #[test]
+    fn reports_what_went_wrong() {
+        let wav = stereo_wav(hound::SampleFormat::Int, 16);
+
+        // cut short in the header and halfway through the samples
+        for len in [20, wav.len() - 3] {
+            let result = read_wav(Cursor::new(&wav[..len]), ChannelMode::Downmix);
+            assert!(matches!(result, Err(ChordError::Decode(_))), "{} bytes: {:?}", len, result);
+        }
+
+        let missing = open_wav("samples/missing.wav", ChannelMode::Downmix);
+        assert!(matches!(missing, Err(ChordError::Io(ref e)) if e.kind() == io::ErrorKind::NotFound));
+    }
+}
@@ -0,0 +1,224 @@
+//! Tempo estimation and beat tracking from the onset detection function, after Ellis, "Beat
+//! Tracking by Dynamic Programming" (2007).
+
+use crate::onset::{self, OnsetDetection};
+
+/// How the tempo is estimated and beats are placed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BeatTracking {
+    /// Slowest tempo considered, in beats per minute.
+    pub min_bpm: f32,
+    /// Fastest tempo considered.
+    pub max_bpm: f32,
+    /// Tempo the estimate leans towards when the onsets fit several, a half or double tempo
+    /// usually fitting almost as well as the right one.
+    pub prior_bpm: f32,
+    /// How strongly beats are kept to the estimated tempo rather than following the onsets.
+    pub tightness: f32,
+    /// Beats grouped into a bar, starting from the first beat.
+    pub beats_per_bar: usize,
+}
+
+impl Default for BeatTracking {
+    fn default() -> BeatTracking {
+        BeatTracking { min_bpm: 40.0, max_bpm: 240.0, prior_bpm: 120.0, tightness: 100.0, beats_per_bar: 4 }
+    }
+}
+
+/// Whether chords are reported per beat or per bar.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Grid {
+    Beat,
+    Bar,
+}
+
+impl Grid {
+    /// Parses `beat` or `bar`.
+    pub fn parse(name: &str) -> Option<Grid> {
+        match name {
+            "beat" => Some(Grid::Beat),
+            "bar" => Some(Grid::Bar),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Grid::Beat => "beat",
+            Grid::Bar => "bar",
+        }
+    }
+}
+
+/// The estimated tempo and where the beats fall.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Beats {
+    pub bpm: f32,
+    /// Beat times in seconds.
+    pub times: Vec<f32>,
+}
+
+impl Beats {
+    /// Times of the first beat of every bar of `beats_per_bar` beats.
+    pub fn bars(&self, beats_per_bar: usize) -> Vec<f32> {
+        self.times.iter().cloned().step_by(beats_per_bar.max(1)).collect()
+    }
+
+    /// Boundaries of the beats or bars of a recording `duration` seconds long, from 0 to the end.
+    /// Anything before the first beat makes an upbeat of its own.
+    pub fn grid(&self, grid: Grid, beats_per_bar: usize, duration: f32) -> Vec<f32> {
+        let times = match grid {
+            Grid::Beat => self.times.clone(),
+            Grid::Bar => self.bars(beats_per_bar),
+        };
+
+        let mut boundaries = vec![0.0];
+        boundaries.extend(times.into_iter().filter(|&time| time > 0.0 && time < duration));
+        boundaries.push(duration);
+        boundaries
+    }
+}
+
+/// Tempo of a detection function sampled `frame_rate` times a second, in beats per minute: the
+/// lag at which it best correlates with itself, weighted towards the prior tempo.
+pub fn estimate_tempo(odf: &[f32], frame_rate: f32, tracking: &BeatTracking) -> f32 {
+    let mean = odf.iter().sum::<f32>() / odf.len().max(1) as f32;
+    let centred: Vec<f32> = odf.iter().map(|v| v - mean).collect();
+
+    let shortest = ((60.0 * frame_rate / tracking.max_bpm.max(1.0)).floor() as usize).max(1);
+    let longest = ((60.0 * frame_rate / tracking.min_bpm.max(1.0)).ceil() as usize).min(odf.len().saturating_sub(1));
+    if shortest + 2 > longest {
+        return tracking.prior_bpm;
+    }
+
+    // a log-normal weighting an octave wide around the prior, as tempo is perceived on a log scale
+    let prior_lag = 60.0 * frame_rate / tracking.prior_bpm;
+    let weighted: Vec<f32> = (shortest - 1..=longest + 1)
+        .map(|lag| {
+            let correlation: f32 = centred.iter().zip(&centred[lag..]).map(|(a, b)| a * b).sum();
+            let octaves = (lag as f32 / prior_lag).log2();
+            correlation / (odf.len() - lag) as f32 * (-0.5 * octaves * octaves).exp()
+        })
+        .collect();
+
+    let best = (1..weighted.len() - 1)
+        .max_by(|&a, &b| weighted[a].total_cmp(&weighted[b]))
+        .unwrap_or(1);
+
+    // the lag is refined between neighbouring frames, whole frames being a few BPM apart
+    let (left, peak, right) = (weighted[best - 1], weighted[best], weighted[best + 1]);
+    let curvature = left - 2.0 * peak + right;
+    let offset = if curvature < 0.0 { 0.5 * (left - right) / curvature } else { 0.0 };
+    let lag = (shortest - 1 + best) as f32 + offset;
+
+    60.0 * frame_rate / lag
+}
+
+/// Frames of the beats of a detection function at `bpm`: the path through its peaks that best
+/// keeps to the tempo, every beat scoring its onset strength and losing `tightness` times the
+/// squared log ratio of its distance from the previous beat to the beat period.
+pub fn track(odf: &[f32], frame_rate: f32, bpm: f32, tightness: f32) -> Vec<usize> {
+    let period = 60.0 * frame_rate / bpm;
+    if odf.is_empty() || period.is_nan() || period < 1.0 {
+        return Vec::new();
+    }
+
+    // onset strength in standard deviations from the mean, so tightness means the same for any
+    // recording and beats placed where nothing happens cost something
+    let mean = odf.iter().sum::<f32>() / odf.len() as f32;
+    let deviation = (odf.iter().map(|v| (v - mean) * (v - mean)).sum::<f32>() / odf.len() as f32).sqrt();
+    let strength: Vec<f32> = odf.iter().map(|v| (v - mean) / deviation.max(f32::MIN_POSITIVE)).collect();
+
+    let nearest = (period / 2.0).round() as usize;
+    let furthest = (2.0 * period).round() as usize;
+
+    let mut scores = strength.clone();
+    let mut previous: Vec<Option<usize>> = vec![None; odf.len()];
+
+    for t in nearest..odf.len() {
+        let best = (t.saturating_sub(furthest)..=t - nearest)
+            .map(|from| {
+                let ratio = ((t - from) as f32 / period).ln();
+                (from, scores[from] - tightness * ratio * ratio)
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+
+        // a path only carries on from beats that were worth it
+        if let Some((from, score)) = best.filter(|&(_, score)| score > 0.0) {
+            scores[t] += score;
+            previous[t] = Some(from);
+        }
+    }
+
+    // the last beat is the best scoring frame near the end, the path having to reach it
+    let tail = odf.len().saturating_sub(furthest);
+    let last = (tail..odf.len()).max_by(|&a, &b| scores[a].total_cmp(&scores[b])).unwrap_or(0);
+
+    let mut beats = vec![last];
+    while let Some(from) = previous[*beats.last().unwrap_or(&0)] {
+        beats.push(from);
+    }
+    beats.reverse();
+
+    // the path runs on at the tempo through whatever is left at either end, so beats there
+    // with less than half the typical onset strength are let go
+    let typical = (beats.iter().map(|&b| odf[b] * odf[b]).sum::<f32>() / beats.len() as f32).sqrt();
+    let first = beats.iter().position(|&b| odf[b] >= typical / 2.0).unwrap_or(beats.len());
+    let last = beats.iter().rposition(|&b| odf[b] >= typical / 2.0).map_or(first, |last| last + 1);
+    beats[first..last].to_vec()
+}
+
+/// The tempo of `samples` and the times of their beats.
+pub fn track_beats(samples: &[f32], sample_rate: usize, detection: &OnsetDetection, tracking: &BeatTracking) -> Beats {
+    let odf = onset::detection_function(samples, sample_rate, detection);
+    let frame_rate = sample_rate as f32 / detection.hop.max(1) as f32;
+
+    let bpm = estimate_tempo(&odf, frame_rate, tracking);
+    let times = track(&odf, frame_rate, bpm, tracking.tightness).into_iter()
+        .map(|frame| frame as f32 / frame_rate)
+        .collect();
+
+    Beats { bpm, times }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recognizer::{ChordRecognizer, Config};
+    use crate::synth::render_chords;
+
+    // four bars of 4/4 at 120 BPM, every beat struck
+    fn four_bars() -> Vec<f32> {
+        let chords: Vec<(&str, f32)> = ["C:maj", "F:maj", "G:maj", "C:maj"].into_iter()
+            .flat_map(|label| [(label, 0.5); 4])
+            .collect();
+        render_chords(&chords).0
+    }
+
+    #[test]
+    fn follows_the_beat() {
+        let samples = four_bars();
+        let beats = track_beats(&samples, 44100, &OnsetDetection::default(), &BeatTracking::default());
+        assert!((beats.bpm - 120.0).abs() < 2.0, "{} BPM", beats.bpm);
+        assert_eq!(beats.times.len(), 16, "{:?}", beats.times);
+        for (i, time) in beats.times.iter().enumerate() {
+            assert!((time - 0.5 * i as f32).abs() < 0.03, "{:?}", beats.times);
+        }
+    }
+
+    #[test]
+    fn one_chord_per_bar() {
+        let samples = four_bars();
+        let recognizer = ChordRecognizer::new(Config::default());
+        let beats = recognizer.beats(&samples, 44100).unwrap();
+
+        let bars = recognizer.beat_segments(&samples, 44100, &beats, Grid::Bar).unwrap();
+        let labels: Vec<String> = bars.iter().map(|bar| bar.chord.harte()).collect();
+        assert_eq!(labels, ["C:maj", "F:maj", "G:maj", "C:maj"]);
+        assert!((bars[2].start - 4.0).abs() < 0.03);
+
+        let per_beat = recognizer.beat_segments(&samples, 44100, &beats, Grid::Beat).unwrap();
+        assert_eq!(per_beat.len(), 16);
+        assert_eq!(per_beat[5].chord.root, Some(5));
+    }
+}
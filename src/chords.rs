@@ -0,0 +1,159 @@
+// chord templates and matching of chroma against them
+
+use crate::chroma::{BASS_NOTES, bass_note};
+use crate::similarity::Similarity;
+use crate::spelling::Spelling;
+use crate::vocabulary::{Quality, Vocabulary};
+
+/// Pitch class names, index 0 being C.
+pub const NOTE_NAMES: [&str; 12] = [
+    "C","C#","D","D#","E","F",
+    "F#","G","G#","A","A#","B"
+];
+
+// interval names used after the slash in Harte labels, by semitones above the root
+const BASS_DEGREES: [&str; 12] = [
+    "1","b2","2","b3","3","4",
+    "b5","5","b6","6","b7","7"
+];
+
+// move the C major/minor template so that the root note corresponds to the chord played
+pub(crate) fn roll_template(template: &[f32;12], shift: usize) -> [f32;12] {
+    let mut out = [0.0;12];
+
+    for i in 0..12 {
+        out[(i + shift) % 12] = template[i];
+    }
+
+    out
+}
+
+// every chord of a vocabulary, laid out root by root ([C major, C minor, C# major, ...])
+// and followed by a single no-chord state
+pub(crate) struct ChordSet {
+    qualities: Vec<&'static Quality>,
+    // one template per chord state, already rolled to its root
+    templates: Vec<[f32; 12]>,
+    similarity: Box<dyn Similarity>,
+}
+
+impl ChordSet {
+    pub(crate) fn new(vocabulary: Vocabulary, similarity: Box<dyn Similarity>) -> ChordSet {
+        let qualities = vocabulary.qualities();
+        let templates = (0..12)
+            .flat_map(|root| qualities.iter().map(move |q| roll_template(&q.template(), root)))
+            .collect();
+
+        ChordSet { qualities, templates, similarity }
+    }
+
+    pub(crate) fn n_states(&self) -> usize {
+        self.templates.len() + 1
+    }
+
+    pub(crate) fn no_chord(&self) -> usize {
+        self.templates.len()
+    }
+
+    // root pitch class and quality of a state, None for no chord
+    pub(crate) fn chord(&self, state: usize) -> Option<(usize, &'static Quality)> {
+        if state >= self.no_chord() {
+            return None;
+        }
+
+        let n = self.qualities.len();
+        Some((state / n, self.qualities[state % n]))
+    }
+
+    // bass note to report alongside a chord state
+    pub(crate) fn bass(&self, state: usize, bass: &[f32; BASS_NOTES]) -> Option<usize> {
+        let (root, _) = self.chord(state)?;
+        bass_note(bass, root)
+    }
+
+    // template score of every chord state, no chord always scores the acceptance threshold
+    pub(crate) fn scores(&self, pitch_energy: &[f32; 12]) -> Vec<f32> {
+        let mut scores: Vec<f32> = self.templates.iter()
+            .map(|template| self.similarity.score(pitch_energy, template))
+            .collect();
+        scores.push(self.similarity.no_chord_score());
+
+        scores
+    }
+
+    // scores for a frame that was gated as silence or noise, where only no chord is possible
+    pub(crate) fn gated_scores(&self) -> Vec<f32> {
+        let mut scores = vec![f32::NEG_INFINITY; self.templates.len()];
+        scores.push(self.similarity.no_chord_score());
+
+        scores
+    }
+
+    // transition weights favouring chords that are close on the circle of fifths,
+    // leaving or entering no chord is equally likely from every chord
+    pub(crate) fn fifths_weights(&self) -> Vec<Vec<f32>> {
+        let n = self.n_states();
+        let mut weights = vec![vec![1.0; n]; n];
+
+        for (from, row) in weights.iter_mut().enumerate() {
+            for (to, weight) in row.iter_mut().enumerate() {
+                let (Some(a), Some(b)) = (self.chord(from), self.chord(to)) else {
+                    continue;
+                };
+
+                let steps = (12 + fifths_position(a) - fifths_position(b)) % 12;
+                let distance = steps.min(12 - steps);
+
+                // a relative major/minor pair shares a position but is still a change of mode,
+                // changing quality on the same root is cheaper than moving along the circle
+                let mode_change = if a.1.is_minor() != b.1.is_minor() { 1.0 } else { 0.0 };
+                let quality_change = if a.1.harte != b.1.harte { 0.5 } else { 0.0 };
+                *weight = (-(distance as f32 + mode_change + quality_change)).exp();
+            }
+        }
+
+        weights
+    }
+}
+
+// e.g. "C major", or "C major/E" when the bass isn't the root
+pub(crate) fn chord_name(chord: Option<(usize, &Quality)>, bass: Option<usize>, spelling: Spelling) -> String {
+    match (chord, bass) {
+        (Some((root, quality)), Some(bass)) => {
+            format!("{} {}/{}", spelling.note(root), quality.name, spelling.bass(root, quality, bass))
+        }
+        (Some((root, quality)), None) => format!("{} {}", spelling.note(root), quality.name),
+        (None, _) => "No chord".to_string(),
+    }
+}
+
+// Harte notation, e.g. G:7 or C:maj/3, with N for no chord
+pub(crate) fn harte_label(chord: Option<(usize, &Quality)>, bass: Option<usize>, spelling: Spelling) -> String {
+    match (chord, bass) {
+        (Some((root, quality)), Some(bass)) => {
+            let degree = BASS_DEGREES[(bass + 12 - root) % 12];
+            format!("{}:{}/{}", spelling.note(root), quality.harte, degree)
+        }
+        (Some((root, quality)), None) => format!("{}:{}", spelling.note(root), quality.harte),
+        (None, _) => "N".to_string(),
+    }
+}
+
+// position of a chord on the circle of fifths, minor chords sit on their relative major
+fn fifths_position((root, quality): (usize, &Quality)) -> usize {
+    let major_root = if quality.is_minor() { (root + 3) % 12 } else { root };
+    (major_root * 7) % 12
+}
+
+// chord matching, the first chord reaching the highest score wins
+pub(crate) fn best_state(scores: &[f32]) -> usize {
+    let mut best = 0;
+
+    for (state, &score) in scores.iter().enumerate() {
+        if score > scores[best] {
+            best = state;
+        }
+    }
+
+    best
+}
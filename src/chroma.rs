@@ -0,0 +1,387 @@
+// turns blocks of audio into pitch-class energy (chroma) vectors
+
+use std::f32::consts::PI;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+use rustfft::{Fft, FftPlanner, num_complex::Complex};
+
+use crate::cqt::ConstantQ;
+
+// frequency band (Hz) folded into the chord chroma
+pub(crate) const TREBLE_RANGE: RangeInclusive<f32> = 70.0..=1500.0;
+
+// the bass is looked for among the semitones from C2 up to G3, anything lower still
+// shows up through its second harmonic while rumble below C2 is left out
+const BASS_LOWEST_MIDI: usize = 36;
+pub(crate) const BASS_NOTES: usize = 20;
+
+// a note counts as sounding in the bass once it reaches this fraction of the loudest bass note,
+// low guitar and piano strings carry much less energy than the notes above them
+const BASS_THRESHOLD: f32 = 0.1;
+
+// bass peaks weaker than this fraction of the strongest peak anywhere in the spectrum are
+// leakage or noise, without it a recording with no bass at all still reports one
+const BASS_FLOOR: f32 = 0.01;
+
+// semitones from a fundamental to its 2nd..8th harmonics
+const HARMONIC_OFFSETS: [usize; 7] = [12, 19, 24, 28, 31, 34, 36];
+
+// amplitude ratio between consecutive harmonics assumed when suppressing overtones
+const HARMONIC_DECAY: f32 = 0.6;
+
+// spectral peaks below this fraction of the strongest are left out of harmonic-aware chroma
+const HARMONIC_FLOOR: f32 = 0.01;
+
+// fractional MIDI note number of a frequency, with A4 tuned to `reference` Hz
+pub(crate) fn freq_to_midi(freq: f32, reference: f32) -> f32 {
+    // TODO log2 as a lookup table for performance
+    69.0 + 12.0 * (freq / reference).log2()
+}
+
+// converts note to pitch (eg. A3 -> A, D2 -> D etc.)
+pub(crate) fn freq_to_pitch(freq: f32, reference: f32) -> usize {
+    let midi = freq_to_midi(freq, reference);
+    // round to nearest semitone
+    let note_number = midi.round() as i32;
+    // convert to pitch class (0–11)
+    let mut pitch_class = note_number % 12;
+
+    if pitch_class < 0 {
+        pitch_class += 12;
+    }
+
+    pitch_class as usize
+}
+
+pub(crate) fn hann_window(n: usize) -> Vec<f32> {
+    (0..n)
+        .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / n as f32).cos())
+        .collect()
+}
+
+// pitch-class energy of a block of audio over the full chord range,
+// plus the energy of each semitone in the bass range, lowest first
+pub(crate) struct Chroma {
+    pub(crate) treble: [f32; 12],
+    pub(crate) bass: [f32; BASS_NOTES],
+    // loudness of the windowed block in dBFS
+    pub(crate) rms_db: f32,
+    // spectral flatness over the chord range, near 0 for clear pitches and towards 1 for noise
+    pub(crate) flatness: f32,
+}
+
+impl Chroma {
+    // pitch-class and bass energy summed over `frames`, their level and flatness averaged
+    pub(crate) fn mean(frames: &[Chroma]) -> Chroma {
+        let mut mean = Chroma { treble: [0.0; 12], bass: [0.0; BASS_NOTES], rms_db: 0.0, flatness: 0.0 };
+        let n = frames.len().max(1) as f32;
+
+        for frame in frames {
+            for (m, f) in mean.treble.iter_mut().zip(frame.treble.iter()) {
+                *m += f;
+            }
+            for (m, f) in mean.bass.iter_mut().zip(frame.bass.iter()) {
+                *m += f;
+            }
+            mean.rms_db += frame.rms_db / n;
+            mean.flatness += frame.flatness / n;
+        }
+
+        normalize(&mut mean.treble);
+        mean
+    }
+}
+
+/// How spectra are turned into chroma.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrontEnd {
+    /// Linear FFT bins mapped straight to pitch classes.
+    Fft,
+    /// Log-spaced constant-Q bins between `min_freq` and `max_freq` Hz.
+    ConstantQ { bins_per_octave: usize, min_freq: f32, max_freq: f32 },
+}
+
+// turns blocks of exactly `frame_size` samples into chroma
+pub(crate) struct ChromaExtractor {
+    sr: usize,
+    // frequency of A4 in Hz
+    reference: f32,
+    window: Vec<f32>,
+    fft: Arc<dyn Fft<f32>>,
+    constant_q: Option<ConstantQ>,
+    // attribute overtones back to their fundamentals before folding into pitch classes
+    harmonics: bool,
+}
+
+impl ChromaExtractor {
+    pub(crate) fn new(
+        sr: usize,
+        frame_size: usize,
+        front_end: FrontEnd,
+        reference: f32,
+        harmonics: bool,
+    ) -> ChromaExtractor {
+        let mut planner = FftPlanner::<f32>::new();
+        let constant_q = match front_end {
+            FrontEnd::Fft => None,
+            FrontEnd::ConstantQ { bins_per_octave, min_freq, max_freq } => {
+                Some(ConstantQ::new(sr, frame_size, min_freq, max_freq, bins_per_octave, reference))
+            }
+        };
+
+        ChromaExtractor {
+            sr,
+            reference,
+            window: hann_window(frame_size),
+            fft: planner.plan_fft_forward(frame_size),
+            constant_q,
+            harmonics,
+        }
+    }
+
+    // windowed FFT of a block of samples, folded into max-normalized pitch-class energy vectors
+    pub(crate) fn chroma(&self, samples: &[f32]) -> Chroma {
+        let n = samples.len();
+
+        // apply Hann window
+        let mut input: Vec<Complex<f32>> = samples.iter()
+            .zip(self.window.iter())
+            .map(|(s,w)| Complex{ re: s*w, im: 0.0 })
+            .collect();
+        // the level of what the window hears, so a chord dying away at the frame's edge doesn't count
+        let level = rms_db(&input.iter().map(|c| c.re).collect::<Vec<f32>>());
+
+        self.fft.process(&mut input);
+
+        let bass = bass_notes(&input[..n/2], self.sr, n, self.reference);
+        let flatness = spectral_flatness(&input[..n/2], self.sr, n);
+
+        let treble = match (&self.constant_q, self.harmonics) {
+            (None, false) => fold_pitch_classes(&input[..n/2], self.sr, n, TREBLE_RANGE, self.reference),
+            (None, true) => {
+                let mags: Vec<f32> = input[..n/2].iter().map(|c| c.norm()).collect();
+                let floor = HARMONIC_FLOOR * mags.iter().cloned().fold(0.0, f32::max);
+
+                let mut notes = [0.0f32; 128];
+                for (freq, mag) in spectral_peaks(&mags, self.sr, n, floor) {
+                    if !TREBLE_RANGE.contains(&freq) {
+                        continue;
+                    }
+                    let midi = freq_to_midi(freq, self.reference).round() as usize;
+                    if let Some(note) = notes.get_mut(midi) {
+                        *note += mag;
+                    }
+                }
+
+                fold_notes(notes)
+            }
+            // the constant-Q kernels carry their own windows, so they need the plain spectrum
+            (Some(constant_q), harmonics) => {
+                let mut input: Vec<Complex<f32>> = samples.iter()
+                    .map(|&s| Complex { re: s, im: 0.0 })
+                    .collect();
+                self.fft.process(&mut input);
+
+                let bins = constant_q.frequencies().iter().zip(constant_q.magnitudes(&input));
+
+                if harmonics {
+                    // several bins per semitone see the same partial, keep the strongest
+                    let mut notes = [0.0f32; 128];
+                    for (&freq, mag) in bins {
+                        let midi = freq_to_midi(freq, self.reference).round() as usize;
+                        if let Some(note) = notes.get_mut(midi) {
+                            *note = note.max(mag);
+                        }
+                    }
+
+                    fold_notes(notes)
+                } else {
+                    let mut pitch_energy = [0.0f32; 12];
+                    for (&freq, mag) in bins {
+                        pitch_energy[freq_to_pitch(freq, self.reference)] += mag;
+                    }
+
+                    normalize(&mut pitch_energy);
+                    pitch_energy
+                }
+            }
+        };
+
+        Chroma { treble, bass, rms_db: level, flatness }
+    }
+}
+
+// root mean square level in dBFS, a full scale sine sitting at -3
+pub(crate) fn rms_db(samples: &[f32]) -> f32 {
+    let mean_square = samples.iter().map(|s| s * s).sum::<f32>() / samples.len().max(1) as f32;
+    10.0 * mean_square.max(1e-20).log10()
+}
+
+// geometric over arithmetic mean of the power spectrum within the chord range.
+// a handful of harmonic peaks keep it close to 0, white noise sits around 0.56
+fn spectral_flatness(spectrum: &[Complex<f32>], sr: usize, n: usize) -> f32 {
+    let powers: Vec<f32> = spectrum.iter()
+        .enumerate()
+        .filter(|&(i, _)| TREBLE_RANGE.contains(&(i as f32 * sr as f32 / n as f32)))
+        .map(|(_, c)| c.norm_sqr() + 1e-20)
+        .collect();
+    if powers.is_empty() {
+        return 0.0;
+    }
+
+    let log_mean = powers.iter().map(|p| p.ln()).sum::<f32>() / powers.len() as f32;
+    let mean = powers.iter().sum::<f32>() / powers.len() as f32;
+    log_mean.exp() / mean
+}
+
+// strips overtones from per-MIDI-note energies, then folds what is left into pitch classes
+fn fold_notes(mut notes: [f32; 128]) -> [f32; 12] {
+    suppress_harmonics(&mut notes);
+
+    let mut pitch_energy = [0.0f32; 12];
+    for (midi, energy) in notes.iter().enumerate() {
+        pitch_energy[midi % 12] += energy;
+    }
+
+    normalize(&mut pitch_energy);
+    pitch_energy
+}
+
+// walks up from the lowest note and takes away the energy its overtones are expected to leave
+// higher up, so the 3rd harmonic stops voting for the fifth and the 5th for the major third.
+// partials are assumed to decay geometrically, and a note never loses more than it has, so
+// what remains of a real chord tone sharing a partial's semitone still counts
+fn suppress_harmonics(notes: &mut [f32; 128]) {
+    for midi in 0..notes.len() {
+        let fundamental = notes[midi];
+        if fundamental <= 0.0 {
+            continue;
+        }
+
+        for (h, &offset) in HARMONIC_OFFSETS.iter().enumerate() {
+            let Some(partial) = notes.get_mut(midi + offset) else {
+                break;
+            };
+            // HARMONIC_OFFSETS starts at the 2nd harmonic
+            let expected = fundamental * HARMONIC_DECAY.powi(h as i32 + 1);
+            *partial -= partial.min(expected);
+        }
+    }
+}
+
+// adds up the magnitude of every bin within `range` Hz into its pitch class
+fn fold_pitch_classes(
+    spectrum: &[Complex<f32>],
+    sr: usize,
+    n: usize,
+    range: RangeInclusive<f32>,
+    reference: f32,
+) -> [f32; 12] {
+    // build pitch-class energy vector
+    let mut pitch_energy = [0.0f32; 12];
+
+    for (k, c) in spectrum.iter().enumerate() {
+        let freq = k as f32 * sr as f32 / n as f32;
+
+        if !range.contains(&freq) {
+            continue;
+        }
+
+        let mag = c.norm();
+
+        let pc = freq_to_pitch(freq, reference);
+        pitch_energy[pc] += mag;
+    }
+
+    normalize(&mut pitch_energy);
+    pitch_energy
+}
+
+// scales the vector so its largest entry is 1
+pub(crate) fn normalize(pitch_energy: &mut [f32; 12]) {
+    if let Some(max_val) = pitch_energy.iter().cloned().reduce(f32::max)
+        && max_val > 0.0
+    {
+        for v in pitch_energy.iter_mut() {
+            *v /= max_val;
+        }
+    }
+}
+
+// (frequency, magnitude) of every local maximum of the spectrum reaching `floor`.
+// bins are too wide at low frequencies to map them to notes directly, so each peak's
+// frequency is refined with parabolic interpolation between its neighbours
+pub(crate) fn spectral_peaks(mags: &[f32], sr: usize, n: usize, floor: f32) -> Vec<(f32, f32)> {
+    let mut peaks = Vec::new();
+
+    for k in 1..mags.len().saturating_sub(1) {
+        let (left, mag, right) = (mags[k - 1], mags[k], mags[k + 1]);
+        if mag <= left || mag < right || mag < floor {
+            continue;
+        }
+
+        let curvature = left - 2.0 * mag + right;
+        let offset = if curvature < 0.0 { 0.5 * (left - right) / curvature } else { 0.0 };
+        peaks.push(((k as f32 + offset) * sr as f32 / n as f32, mag));
+    }
+
+    peaks
+}
+
+// magnitude of every spectral peak in the bass range, added up per semitone
+fn bass_notes(spectrum: &[Complex<f32>], sr: usize, n: usize, reference: f32) -> [f32; BASS_NOTES] {
+    let mut energy = [0.0f32; BASS_NOTES];
+    let mags: Vec<f32> = spectrum.iter().map(|c| c.norm()).collect();
+    let floor = BASS_FLOOR * mags.iter().cloned().fold(0.0, f32::max);
+
+    for (freq, mag) in spectral_peaks(&mags, sr, n, floor) {
+        let midi = freq_to_midi(freq, reference).round();
+
+        if midi < BASS_LOWEST_MIDI as f32 {
+            continue;
+        }
+
+        let note = midi as usize - BASS_LOWEST_MIDI;
+        if note >= BASS_NOTES {
+            break;
+        }
+
+        energy[note] += mag;
+    }
+
+    energy
+}
+
+// pitch class of the lowest clearly sounding bass note, if it isn't already the root of the chord
+pub(crate) fn bass_note(bass: &[f32; BASS_NOTES], root: usize) -> Option<usize> {
+    let loudest = bass.iter().cloned().fold(0.0, f32::max);
+    if loudest <= 0.0 {
+        return None;
+    }
+
+    let lowest = bass.iter().position(|&e| e >= BASS_THRESHOLD * loudest)?;
+    let pc = (lowest + BASS_LOWEST_MIDI) % 12;
+
+    if pc == root {
+        return None;
+    }
+
+    Some(pc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synth::{ChordSpec, Synth};
+
+    // a far-off reference puts peaks beyond the last MIDI note, which used to index past the end
+    #[test]
+    fn harmonics_survive_any_reference() {
+        let samples = Synth::default().render(&ChordSpec::default());
+        for reference in [10.0, 440.0, 100_000.0] {
+            let extractor = ChromaExtractor::new(44100, samples.len(), FrontEnd::Fft, reference, true);
+            extractor.chroma(&samples);
+        }
+    }
+}
@@ -0,0 +1,89 @@
+// constant-Q transform with log-spaced bins, computed from an FFT with precomputed spectral kernels
+// (Brown & Puckette), so every semitone gets the same number of bins at any height
+
+use std::f32::consts::PI;
+
+use rustfft::{FftPlanner, num_complex::Complex};
+
+// spectral kernel entries below this fraction of the kernel peak are dropped
+const KERNEL_THRESHOLD: f32 = 0.0054;
+
+pub struct ConstantQ {
+    // centre frequency of every bin, lowest first
+    frequencies: Vec<f32>,
+    // sparse conjugated spectral kernels as (fft bin, weight)
+    kernels: Vec<Vec<(usize, Complex<f32>)>>,
+    frame_size: usize,
+}
+
+impl ConstantQ {
+    // bins start on the first semitone (with A4 at `reference` Hz) at or above `min_freq`
+    // and run up to `max_freq`.
+    // kernels longer than the frame are cut down to it, trading low-end resolution for time
+    pub fn new(
+        sr: usize,
+        frame_size: usize,
+        min_freq: f32,
+        max_freq: f32,
+        bins_per_octave: usize,
+        reference: f32,
+    ) -> ConstantQ {
+        let bins_per_octave = bins_per_octave.max(1);
+        let q = 1.0 / (2f32.powf(1.0 / bins_per_octave as f32) - 1.0);
+
+        let first_midi = (69.0 + 12.0 * (min_freq / reference).log2()).ceil();
+        let first = reference * 2f32.powf((first_midi - 69.0) / 12.0);
+
+        let frequencies: Vec<f32> = (0..)
+            .map(|b| first * 2f32.powf(b as f32 / bins_per_octave as f32))
+            .take_while(|&f| f <= max_freq && f < sr as f32 / 2.0)
+            .collect();
+
+        let mut planner = FftPlanner::<f32>::new();
+        let fft = planner.plan_fft_forward(frame_size);
+
+        let kernels = frequencies.iter()
+            .map(|&freq| {
+                let len = ((q * sr as f32 / freq).ceil() as usize).clamp(1, frame_size);
+                let offset = (frame_size - len) / 2;
+
+                // Hann windowed complex sinusoid centred in the frame
+                let mut kernel = vec![Complex { re: 0.0, im: 0.0 }; frame_size];
+                for i in 0..len {
+                    let w = 0.5 - 0.5 * (2.0 * PI * i as f32 / len as f32).cos();
+                    let phase = 2.0 * PI * freq * i as f32 / sr as f32;
+                    kernel[offset + i] = Complex::from_polar(w / len as f32, phase);
+                }
+
+                fft.process(&mut kernel);
+
+                let peak = kernel.iter().map(|c| c.norm()).fold(0.0, f32::max);
+                kernel.iter()
+                    .enumerate()
+                    .filter(|(_, c)| c.norm() > KERNEL_THRESHOLD * peak)
+                    .map(|(k, c)| (k, c.conj() / frame_size as f32))
+                    .collect()
+            })
+            .collect();
+
+        ConstantQ { frequencies, kernels, frame_size }
+    }
+
+    pub fn frequencies(&self) -> &[f32] {
+        &self.frequencies
+    }
+
+    // magnitude of every constant-Q bin, given the FFT of an unwindowed frame
+    pub fn magnitudes(&self, spectrum: &[Complex<f32>]) -> Vec<f32> {
+        debug_assert_eq!(spectrum.len(), self.frame_size);
+
+        self.kernels.iter()
+            .map(|kernel| {
+                kernel.iter()
+                    .map(|&(k, weight)| spectrum[k] * weight)
+                    .sum::<Complex<f32>>()
+                    .norm()
+            })
+            .collect()
+    }
+}
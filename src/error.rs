@@ -0,0 +1,67 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use crate::tuning::REFERENCE_RANGE;
+
+/// Everything that can go wrong between reading audio and recognizing a chord.
+#[derive(Debug)]
+pub enum ChordError {
+    /// The input could not be opened or read.
+    Io(io::Error),
+    /// The input is not valid WAV data, or ends early.
+    Decode(String),
+    /// Valid WAV data in a sample format that isn't supported, e.g. 12-bit integer.
+    UnsupportedFormat(String),
+    /// A channel was requested that the input doesn't have.
+    InvalidChannel { requested: usize, available: usize },
+    /// There are no samples to analyze.
+    EmptyInput,
+    /// Every sample is (close to) zero, so there is nothing to hear.
+    SilentInput,
+    /// A sample is NaN or infinite.
+    NonFinite,
+    /// A chord annotation line or label that can't be parsed.
+    InvalidAnnotation(String),
+    /// A frequency of A4 outside `tuning::REFERENCE_RANGE`.
+    InvalidReference(f32),
+    /// An output file could not be created or written.
+    Write(io::Error),
+}
+
+impl fmt::Display for ChordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChordError::Io(e) => write!(f, "failed to read input: {}", e),
+            ChordError::Decode(e) => write!(f, "failed to decode input: {}", e),
+            ChordError::UnsupportedFormat(format) => write!(f, "unsupported sample format: {}", format),
+            ChordError::InvalidChannel { requested, available } => {
+                write!(f, "channel {} requested but the input has {}", requested, available)
+            }
+            ChordError::EmptyInput => write!(f, "input contains no samples"),
+            ChordError::SilentInput => write!(f, "input is silent"),
+            ChordError::NonFinite => write!(f, "input contains NaN or infinite samples"),
+            ChordError::InvalidAnnotation(e) => write!(f, "invalid annotation: {}", e),
+            ChordError::InvalidReference(hz) => {
+                let (low, high) = (REFERENCE_RANGE.start(), REFERENCE_RANGE.end());
+                write!(f, "reference frequency {} Hz is outside {} to {} Hz", hz, low, high)
+            }
+            ChordError::Write(e) => write!(f, "failed to write output: {}", e),
+        }
+    }
+}
+
+impl Error for ChordError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ChordError::Io(e) | ChordError::Write(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ChordError {
+    fn from(e: io::Error) -> ChordError {
+        ChordError::Io(e)
+    }
+}
@@ -0,0 +1,330 @@
+//! MIREX-style scoring of estimated chord annotations against reference ones.
+//!
+//! Chord accuracy is the weighted chord symbol recall (WCSR): the fraction of the annotated time
+//! over which the estimate matches the reference, with what counts as a match depending on the
+//! [`Level`]. Segmentation quality is measured with the directional Hamming distance between the
+//! segment boundaries, ignoring labels.
+
+use std::collections::BTreeMap;
+
+use crate::error::ChordError;
+use crate::harte::{self, Label};
+use crate::lab::Annotation;
+
+// qualities references must have to take part at the majmin and sevenths levels,
+// as bitmaps of semitones above the root
+const MAJ: u16 = 0b0000_1001_0001;
+const MIN: u16 = 0b0000_1000_1001;
+const MAJ7: u16 = 0b1000_1001_0001;
+const DOM7: u16 = 0b0100_1001_0001;
+const MIN7: u16 = 0b0100_1000_1001;
+
+// the majmin and triads levels only look at the intervals up to the fifth
+const TRIAD_MASK: u16 = 0b0000_1111_1111;
+
+// the mirex level wants this many shared pitch classes
+const MIREX_SHARED_NOTES: u32 = 3;
+
+/// How closely an estimated chord has to match the reference to count as correct.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Level {
+    /// Same root.
+    Root,
+    /// Same root and major/minor triad. Only references that are major, minor or N count.
+    MajMin,
+    /// Same root and triad, extensions ignored.
+    Triads,
+    /// Same root and intervals. Only references that are major, minor, maj7, 7, min7 or N count.
+    Sevenths,
+    /// At least three pitch classes in common with the reference.
+    Mirex,
+}
+
+impl Level {
+    pub const ALL: [Level; 5] = [Level::Root, Level::MajMin, Level::Triads, Level::Sevenths, Level::Mirex];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Root => "root",
+            Level::MajMin => "majmin",
+            Level::Triads => "triads",
+            Level::Sevenths => "sevenths",
+            Level::Mirex => "mirex",
+        }
+    }
+
+    // whether the estimate is correct, None when the reference doesn't take part at this level.
+    // X never does, bass notes are only ever considered through the pitch classes they add
+    fn compare(self, reference: &Label, estimate: &Label) -> Option<bool> {
+        if *reference == Label::Unknown {
+            return None;
+        }
+
+        let same_root = reference.root() == estimate.root();
+        let (ref_intervals, est_intervals) = (reference.intervals(), estimate.intervals());
+
+        match self {
+            Level::Root => Some(same_root),
+            Level::MajMin => {
+                let triad = ref_intervals & TRIAD_MASK;
+                if *reference != Label::NoChord && triad != MAJ && triad != MIN {
+                    return None;
+                }
+                Some(same_root && triad == est_intervals & TRIAD_MASK)
+            }
+            Level::Triads => Some(same_root && ref_intervals & TRIAD_MASK == est_intervals & TRIAD_MASK),
+            Level::Sevenths => {
+                if *reference != Label::NoChord && ![MAJ, MIN, MAJ7, DOM7, MIN7].contains(&ref_intervals) {
+                    return None;
+                }
+                Some(same_root && ref_intervals == est_intervals)
+            }
+            Level::Mirex => {
+                let n_notes = reference.pitch_classes().count_ones();
+                if n_notes > 0 && n_notes < MIREX_SHARED_NOTES {
+                    return None;
+                }
+                if *reference == Label::NoChord {
+                    return Some(*estimate == Label::NoChord);
+                }
+                let shared = reference.pitch_classes() & estimate.pitch_classes();
+                Some(shared.count_ones() >= MIREX_SHARED_NOTES)
+            }
+        }
+    }
+
+    fn index(self) -> usize {
+        Level::ALL.iter().position(|&level| level == self).unwrap_or(0)
+    }
+}
+
+/// Scores of one or more annotated recordings. Evaluations of several files can be combined
+/// with [`Evaluation::merge`], weighting every file by its annotated duration.
+#[derive(Clone, Debug, Default)]
+pub struct Evaluation {
+    duration: f32,
+    // seconds judged correct and seconds taking part, per level
+    correct: [f32; 5],
+    counted: [f32; 5],
+    // segmentation scores multiplied by duration, so they merge into a weighted mean
+    over_segmentation: f32,
+    under_segmentation: f32,
+    confusion: BTreeMap<String, BTreeMap<String, f32>>,
+}
+
+impl Evaluation {
+    /// Annotated time that was evaluated, in seconds.
+    pub fn duration(&self) -> f32 {
+        self.duration
+    }
+
+    /// Weighted chord symbol recall at `level` between 0 and 1, None when no reference chord
+    /// takes part at that level.
+    pub fn wcsr(&self, level: Level) -> Option<f32> {
+        let i = level.index();
+        (self.counted[i] > 0.0).then(|| self.correct[i] / self.counted[i])
+    }
+
+    /// 1 minus the directional Hamming distance from the reference segments to the estimated
+    /// ones. Low when the estimate splits reference segments up.
+    pub fn over_segmentation(&self) -> Option<f32> {
+        (self.duration > 0.0).then(|| self.over_segmentation / self.duration)
+    }
+
+    /// 1 minus the directional Hamming distance from the estimated segments to the reference
+    /// ones. Low when the estimate merges reference segments.
+    pub fn under_segmentation(&self) -> Option<f32> {
+        (self.duration > 0.0).then(|| self.under_segmentation / self.duration)
+    }
+
+    /// The lower of over- and under-segmentation.
+    pub fn segmentation(&self) -> Option<f32> {
+        Some(self.over_segmentation()?.min(self.under_segmentation()?))
+    }
+
+    /// Seconds of each reference chord (outer key) that were labeled as each estimated chord
+    /// (inner key). Roots are spelled with sharps and bass notes are dropped, e.g. `A#:maj7`.
+    pub fn confusion(&self) -> &BTreeMap<String, BTreeMap<String, f32>> {
+        &self.confusion
+    }
+
+    /// Adds the scores of another evaluation, as if both had been evaluated at once.
+    pub fn merge(&mut self, other: &Evaluation) {
+        self.duration += other.duration;
+        for i in 0..Level::ALL.len() {
+            self.correct[i] += other.correct[i];
+            self.counted[i] += other.counted[i];
+        }
+        self.over_segmentation += other.over_segmentation;
+        self.under_segmentation += other.under_segmentation;
+
+        for (reference, row) in &other.confusion {
+            let ours = self.confusion.entry(reference.clone()).or_default();
+            for (estimate, seconds) in row {
+                *ours.entry(estimate.clone()).or_default() += seconds;
+            }
+        }
+    }
+}
+
+/// Scores `estimate` against `reference`. Only time covered by the reference is evaluated,
+/// and estimates missing over part of it count as `N` there.
+pub fn evaluate(reference: &[Annotation], estimate: &[Annotation]) -> Result<Evaluation, ChordError> {
+    let reference = parsed(reference)?;
+    let estimate = parsed(estimate)?;
+
+    let mut evaluation = Evaluation::default();
+    let (Some(first), Some(last)) = (reference.first(), reference.iter().map(|a| a.end).reduce(f32::max))
+    else {
+        return Ok(evaluation);
+    };
+    let (t0, t1) = (first.start, last);
+
+    // the estimate clipped to the reference, so its segments line up for segmentation scores
+    let clipped: Vec<Parsed> = estimate.iter()
+        .filter(|a| a.end > t0 && a.start < t1)
+        .map(|a| Parsed { start: a.start.max(t0), end: a.end.min(t1), ..a.clone() })
+        .collect();
+
+    // every stretch between two consecutive boundaries of either annotation has one label in each
+    let mut times: Vec<f32> = reference.iter().chain(&clipped)
+        .flat_map(|a| [a.start, a.end])
+        .collect();
+    times.sort_by(f32::total_cmp);
+    times.dedup();
+
+    let no_chord = Parsed { start: t0, end: t1, label: "N".to_string(), chord: Label::NoChord };
+
+    for pair in times.windows(2) {
+        let (a, b) = (pair[0], pair[1]);
+        let middle = (a + b) / 2.0;
+        let Some(ref_chord) = covering(&reference, middle) else {
+            continue;
+        };
+        let est_chord = covering(&clipped, middle).unwrap_or(&no_chord);
+
+        let seconds = b - a;
+        evaluation.duration += seconds;
+        for level in Level::ALL {
+            if let Some(correct) = level.compare(&ref_chord.chord, &est_chord.chord) {
+                evaluation.counted[level.index()] += seconds;
+                if correct {
+                    evaluation.correct[level.index()] += seconds;
+                }
+            }
+        }
+
+        *evaluation.confusion
+            .entry(harte::canonical(&ref_chord.label))
+            .or_default()
+            .entry(harte::canonical(&est_chord.label))
+            .or_default() += seconds;
+    }
+
+    let ref_intervals: Vec<(f32, f32)> = reference.iter().map(|a| (a.start, a.end)).collect();
+    let est_intervals: Vec<(f32, f32)> = clipped.iter().map(|a| (a.start, a.end)).collect();
+    evaluation.over_segmentation =
+        (1.0 - directional_hamming(&ref_intervals, &est_intervals)) * evaluation.duration;
+    evaluation.under_segmentation =
+        (1.0 - directional_hamming(&est_intervals, &ref_intervals)) * evaluation.duration;
+
+    Ok(evaluation)
+}
+
+// an annotation along with its parsed label
+#[derive(Clone)]
+struct Parsed {
+    start: f32,
+    end: f32,
+    label: String,
+    chord: Label,
+}
+
+// parses every label and sorts the annotations by start time
+fn parsed(annotations: &[Annotation]) -> Result<Vec<Parsed>, ChordError> {
+    let mut parsed = annotations.iter()
+        .map(|a| {
+            let chord = harte::parse(&a.label).map_err(ChordError::InvalidAnnotation)?;
+            Ok(Parsed { start: a.start, end: a.end, label: a.label.clone(), chord })
+        })
+        .collect::<Result<Vec<_>, ChordError>>()?;
+
+    parsed.sort_by(|a, b| a.start.total_cmp(&b.start));
+    Ok(parsed)
+}
+
+// the annotation covering time `t`, if any
+fn covering(annotations: &[Parsed], t: f32) -> Option<&Parsed> {
+    let i = annotations.partition_point(|a| a.start <= t);
+    annotations[..i].iter().rev().find(|a| a.end > t)
+}
+
+// how much of `from` has to be cut off so each of its segments lies within one segment of `to`,
+// as a fraction of the duration of `from`
+fn directional_hamming(from: &[(f32, f32)], to: &[(f32, f32)]) -> f32 {
+    let (Some(first), Some(last)) = (from.first(), from.last()) else {
+        return 0.0;
+    };
+    let span = last.1 - first.0;
+    if span <= 0.0 {
+        return 0.0;
+    }
+
+    let mut boundaries: Vec<f32> = to.iter().flat_map(|&(start, end)| [start, end]).collect();
+    boundaries.sort_by(f32::total_cmp);
+    boundaries.dedup();
+
+    let mut cut = 0.0;
+    for &(start, end) in from {
+        let mut points = vec![start];
+        points.extend(boundaries.iter().filter(|&&t| t > start && t < end));
+        points.push(end);
+
+        let longest = points.windows(2).map(|pair| pair[1] - pair[0]).fold(0.0, f32::max);
+        cut += (end - start) - longest;
+    }
+
+    cut / span
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn annotations(lines: &[(f32, f32, &str)]) -> Vec<Annotation> {
+        lines.iter()
+            .map(|&(start, end, label)| Annotation { start, end, label: label.to_string() })
+            .collect()
+    }
+
+    #[test]
+    fn levels_follow_mirex_rules() {
+        let reference = annotations(&[(0.0, 1.0, "C:maj7"), (1.0, 2.0, "A:min/b3"), (2.0, 3.0, "X")]);
+        let estimate = annotations(&[(0.0, 1.0, "C:maj"), (1.0, 2.5, "C:maj")]);
+        let evaluation = evaluate(&reference, &estimate).unwrap();
+
+        // X is evaluated at no level
+        assert_eq!(evaluation.duration(), 3.0);
+        assert_eq!(evaluation.wcsr(Level::Root), Some(0.5));
+        assert_eq!(evaluation.wcsr(Level::MajMin), Some(0.5));
+        // C:maj7 counts as a major chord at majmin and triads, but not at sevenths
+        assert_eq!(evaluation.wcsr(Level::Sevenths), Some(0.0));
+        // C:maj7 shares C, E and G with C:maj, A:min/b3 only C and E
+        assert_eq!(evaluation.wcsr(Level::Mirex), Some(0.5));
+    }
+
+    #[test]
+    fn segmentation_penalizes_split_and_merged_segments() {
+        let reference = annotations(&[(0.0, 2.0, "C:maj"), (2.0, 4.0, "G:maj")]);
+
+        let split = annotations(&[(0.0, 1.0, "C:maj"), (1.0, 2.0, "C:min"), (2.0, 4.0, "G:maj")]);
+        let evaluation = evaluate(&reference, &split).unwrap();
+        assert_eq!(evaluation.over_segmentation(), Some(0.75));
+        assert_eq!(evaluation.under_segmentation(), Some(1.0));
+
+        let merged = annotations(&[(0.0, 4.0, "C:maj")]);
+        let evaluation = evaluate(&reference, &merged).unwrap();
+        assert_eq!(evaluation.over_segmentation(), Some(1.0));
+        assert_eq!(evaluation.under_segmentation(), Some(0.5));
+    }
+}
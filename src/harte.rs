@@ -0,0 +1,195 @@
+// parsing of Harte chord labels ("A:min7/b3", "C:(1,3,5)", "N") into pitch content,
+// so annotations written by other tools can be compared with ours
+
+use crate::chords::NOTE_NAMES;
+
+// semitones above the root of the natural scale degrees 1 to 7
+const SCALE_DEGREES: [i32; 7] = [0, 2, 4, 5, 7, 9, 11];
+
+// shorthands from Harte et al. (2005) and the MIREX extensions, as semitones above the root
+const SHORTHANDS: [(&str, &[usize]); 24] = [
+    ("maj", &[0, 4, 7]),
+    ("min", &[0, 3, 7]),
+    ("dim", &[0, 3, 6]),
+    ("aug", &[0, 4, 8]),
+    ("maj7", &[0, 4, 7, 11]),
+    ("min7", &[0, 3, 7, 10]),
+    ("7", &[0, 4, 7, 10]),
+    ("dim7", &[0, 3, 6, 9]),
+    ("hdim7", &[0, 3, 6, 10]),
+    ("minmaj7", &[0, 3, 7, 11]),
+    ("maj6", &[0, 4, 7, 9]),
+    ("min6", &[0, 3, 7, 9]),
+    ("9", &[0, 2, 4, 7, 10]),
+    ("maj9", &[0, 2, 4, 7, 11]),
+    ("min9", &[0, 2, 3, 7, 10]),
+    ("11", &[0, 2, 4, 5, 7, 10]),
+    ("min11", &[0, 2, 3, 5, 7, 10]),
+    ("13", &[0, 2, 4, 5, 7, 9, 10]),
+    ("maj13", &[0, 2, 4, 5, 7, 9, 11]),
+    ("min13", &[0, 2, 3, 5, 7, 9, 10]),
+    ("sus2", &[0, 2, 7]),
+    ("sus4", &[0, 5, 7]),
+    ("5", &[0, 7]),
+    ("1", &[0]),
+];
+
+// the pitch content of a chord label
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Label {
+    // N, silence or no harmony
+    NoChord,
+    // X, a chord that can't be described, left out of evaluation
+    Unknown,
+    Chord {
+        root: usize,
+        // bit i set when the pitch class i semitones above the root sounds, bass included
+        intervals: u16,
+        // semitones above the root
+        bass: usize,
+    },
+}
+
+impl Label {
+    pub(crate) fn root(&self) -> Option<usize> {
+        match self {
+            Label::Chord { root, .. } => Some(*root),
+            _ => None,
+        }
+    }
+
+    // intervals above the root, empty for N and X
+    pub(crate) fn intervals(&self) -> u16 {
+        match self {
+            Label::Chord { intervals, .. } => *intervals,
+            _ => 0,
+        }
+    }
+
+    // sounding pitch classes, bit 0 being C
+    pub(crate) fn pitch_classes(&self) -> u16 {
+        match self {
+            Label::Chord { root, intervals, .. } => {
+                let rotated = (*intervals as u32) << root;
+                ((rotated | rotated >> 12) & 0xfff) as u16
+            }
+            _ => 0,
+        }
+    }
+}
+
+pub(crate) fn parse(label: &str) -> Result<Label, String> {
+    let label = label.trim();
+    match label {
+        "N" => return Ok(Label::NoChord),
+        "X" => return Ok(Label::Unknown),
+        _ => {}
+    }
+
+    let invalid = || format!("invalid chord label {:?}", label);
+
+    let (chord, bass) = match label.split_once('/') {
+        Some((chord, bass)) => (chord, Some(bass)),
+        None => (label, None),
+    };
+
+    let (root, rest) = parse_note(chord).ok_or_else(invalid)?;
+
+    // without a shorthand or degree list the chord is major, "C" and "C/3" being C:maj
+    let (shorthand, degrees) = if let Some(rest) = rest.strip_prefix(':') {
+        match rest.split_once('(') {
+            Some((shorthand, degrees)) => (shorthand, Some(degrees.strip_suffix(')').ok_or_else(invalid)?)),
+            None => (rest, None),
+        }
+    } else if rest.is_empty() {
+        ("maj", None)
+    } else {
+        return Err(invalid());
+    };
+
+    let mut intervals: u16 = 1;
+    if !shorthand.is_empty() {
+        let (_, semitones) = SHORTHANDS.iter().find(|(name, _)| *name == shorthand).ok_or_else(invalid)?;
+        for &semitone in *semitones {
+            intervals |= 1 << semitone;
+        }
+    } else if degrees.is_none() {
+        return Err(invalid());
+    }
+
+    for degree in degrees.into_iter().flat_map(|d| d.split(',')).map(str::trim) {
+        // a leading * removes the degree from the shorthand
+        let (omit, degree) = match degree.strip_prefix('*') {
+            Some(degree) => (true, degree),
+            None => (false, degree),
+        };
+        let semitone = parse_degree(degree).ok_or_else(invalid)?;
+        if omit {
+            intervals &= !(1 << semitone);
+        } else {
+            intervals |= 1 << semitone;
+        }
+    }
+
+    let bass = match bass {
+        Some(bass) => parse_degree(bass).ok_or_else(invalid)?,
+        None => 0,
+    };
+    intervals |= 1 << bass;
+
+    Ok(Label::Chord { root, intervals, bass })
+}
+
+// label with the root spelled like our own output and the bass dropped, e.g. "Bb:maj7/3" -> "A#:maj7"
+pub(crate) fn canonical(label: &str) -> String {
+    let label = label.trim();
+    let chord = label.split_once('/').map_or(label, |(chord, _)| chord);
+
+    match parse_note(chord) {
+        Some((root, "")) => format!("{}:maj", NOTE_NAMES[root]),
+        Some((root, rest)) => format!("{}{}", NOTE_NAMES[root], rest),
+        None => chord.to_string(),
+    }
+}
+
+// a note name with its modifiers, e.g. "Bb", returning the pitch class and the rest of the label
+pub(crate) fn parse_note(s: &str) -> Option<(usize, &str)> {
+    let mut chars = s.chars();
+    let natural = match chars.next()? {
+        'C' => 0,
+        'D' => 2,
+        'E' => 4,
+        'F' => 5,
+        'G' => 7,
+        'A' => 9,
+        'B' => 11,
+        _ => return None,
+    };
+
+    let rest = chars.as_str();
+    let modifiers = rest.len() - rest.trim_start_matches(['#', 'b']).len();
+    let shift = modifiers_shift(&rest[..modifiers]);
+
+    Some(((natural + shift).rem_euclid(12) as usize, &rest[modifiers..]))
+}
+
+// a scale degree like "b3" or "#11", as semitones above the root within an octave
+fn parse_degree(s: &str) -> Option<usize> {
+    let number_start = s.find(|c: char| c.is_ascii_digit())?;
+    let (modifiers, number) = s.split_at(number_start);
+    if modifiers.chars().any(|c| c != '#' && c != 'b') {
+        return None;
+    }
+
+    let number: usize = number.parse().ok()?;
+    if number == 0 {
+        return None;
+    }
+
+    let semitones = SCALE_DEGREES[(number - 1) % 7] + modifiers_shift(modifiers);
+    Some(semitones.rem_euclid(12) as usize)
+}
+
+fn modifiers_shift(modifiers: &str) -> i32 {
+    modifiers.chars().map(|c| if c == '#' { 1 } else { -1 }).sum()
+}
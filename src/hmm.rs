@@ -0,0 +1,125 @@
+// hidden Markov model over chord states, decoded with Viterbi
+//
+// all probabilities are kept as natural logs so long recordings don't underflow
+
+pub struct Hmm {
+    log_initial: Vec<f32>,
+    // log_transitions[from][to]
+    log_transitions: Vec<Vec<f32>>,
+}
+
+impl Hmm {
+    // every state stays put with probability `self_prob`, the rest is spread evenly over the others
+    pub fn uniform(n_states: usize, self_prob: f32) -> Hmm {
+        let weights = vec![vec![1.0; n_states]; n_states];
+        Hmm::from_weights(&weights, self_prob)
+    }
+
+    // `weights[from][to]` gives the relative likelihood of moving between two different states,
+    // the diagonal is ignored and replaced by `self_prob`
+    pub fn from_weights(weights: &[Vec<f32>], self_prob: f32) -> Hmm {
+        let n = weights.len();
+        let self_prob = self_prob.clamp(0.0, 1.0);
+
+        let log_transitions = weights.iter()
+            .enumerate()
+            .map(|(from, row)| {
+                let off_diagonal: f32 = row.iter()
+                    .enumerate()
+                    .filter(|&(to, _)| to != from)
+                    .map(|(_, w)| w.max(0.0))
+                    .sum();
+
+                row.iter()
+                    .enumerate()
+                    .map(|(to, w)| {
+                        let p = if to == from {
+                            self_prob
+                        } else if off_diagonal > 0.0 {
+                            (1.0 - self_prob) * w.max(0.0) / off_diagonal
+                        } else {
+                            0.0
+                        };
+                        p.ln()
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Hmm {
+            log_initial: vec![-(n as f32).ln(); n],
+            log_transitions,
+        }
+    }
+
+    pub fn n_states(&self) -> usize {
+        self.log_initial.len()
+    }
+
+    // most likely state sequence given per-frame log emission likelihoods
+    pub fn viterbi(&self, log_emissions: &[Vec<f32>]) -> Vec<usize> {
+        let n = self.n_states();
+        if log_emissions.is_empty() || n == 0 {
+            return Vec::new();
+        }
+
+        let mut score: Vec<f32> = (0..n)
+            .map(|s| self.log_initial[s] + log_emissions[0][s])
+            .collect();
+        // backpointers[t][s] is the best predecessor of state s at frame t
+        let mut backpointers: Vec<Vec<usize>> = Vec::with_capacity(log_emissions.len());
+
+        for emission in &log_emissions[1..] {
+            let mut next = vec![f32::NEG_INFINITY; n];
+            let mut pointers = vec![0; n];
+
+            for to in 0..n {
+                for (from, log_transitions) in self.log_transitions.iter().enumerate() {
+                    let candidate = score[from] + log_transitions[to];
+                    if candidate > next[to] {
+                        next[to] = candidate;
+                        pointers[to] = from;
+                    }
+                }
+                next[to] += emission[to];
+            }
+
+            score = next;
+            backpointers.push(pointers);
+        }
+
+        // trace back from the best final state
+        let mut state = (0..n)
+            .max_by(|&a, &b| score[a].total_cmp(&score[b]))
+            .unwrap_or(0);
+        let mut path = vec![state; log_emissions.len()];
+
+        for (t, pointers) in backpointers.iter().enumerate().rev() {
+            state = pointers[state];
+            path[t] = state;
+        }
+
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // one frame leaning the other way isn't worth two changes of state
+    #[test]
+    fn viterbi_smooths_out_blips() {
+        let steady = vec![0.8f32.ln(), 0.2f32.ln()];
+        let blip = vec![0.3f32.ln(), 0.7f32.ln()];
+        let mut log_emissions = vec![steady; 9];
+        log_emissions[4] = blip;
+
+        let hmm = Hmm::uniform(2, 0.9);
+        assert_eq!(hmm.viterbi(&log_emissions), vec![0; 9]);
+
+        // without any pull to stay put the blip is decoded as it is
+        let loose = Hmm::uniform(2, 0.5);
+        assert_eq!(loose.viterbi(&log_emissions)[4], 1);
+    }
+}
@@ -0,0 +1,139 @@
+//! Harmonic/percussive source separation by median filtering the spectrogram, after Fitzgerald,
+//! "Harmonic/Percussive Separation using Median Filtering" (2010). Sustained notes make
+//! horizontal lines in a spectrogram and drum hits vertical ones, so a median along time keeps
+//! the first and a median along frequency the second.
+
+use crate::stft::{istft, stft};
+
+/// How the spectrogram is split.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hpss {
+    /// Frame length in samples.
+    pub frame_size: usize,
+    /// Distance between frames in samples.
+    pub hop: usize,
+    /// Frames the harmonic median runs over along time.
+    pub harmonic_width: usize,
+    /// Bins the percussive median runs over along frequency.
+    pub percussive_width: usize,
+    /// Exponent of the soft masks, higher gives every bin more decidedly to one part or the other.
+    pub power: f32,
+}
+
+impl Default for Hpss {
+    fn default() -> Hpss {
+        Hpss { frame_size: 4096, hop: 1024, harmonic_width: 17, percussive_width: 17, power: 2.0 }
+    }
+}
+
+/// The two parts of a signal, adding up to it again.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Separation {
+    pub harmonic: Vec<f32>,
+    pub percussive: Vec<f32>,
+}
+
+/// Splits `samples` into what is sustained and what is struck. Every bin of the spectrogram is
+/// shared between the two parts according to how its harmonic and percussive medians compare.
+pub fn separate(samples: &[f32], hpss: &Hpss) -> Separation {
+    let Hpss { frame_size, hop, .. } = *hpss;
+    if frame_size == 0 || hop == 0 {
+        return Separation { harmonic: samples.to_vec(), percussive: vec![0.0; samples.len()] };
+    }
+
+    let spectra = stft(samples, frame_size, hop);
+
+    // the upper half of every spectrum mirrors the lower one
+    let bins = frame_size / 2 + 1;
+    let magnitudes: Vec<Vec<f32>> = spectra.iter()
+        .map(|spectrum| spectrum[..bins].iter().map(|c| c.norm()).collect())
+        .collect();
+
+    let along_time = hpss.harmonic_width / 2;
+    let along_frequency = hpss.percussive_width / 2;
+    let mut values = Vec::with_capacity(hpss.harmonic_width.max(hpss.percussive_width) + 1);
+
+    let masks: Vec<Vec<f32>> = (0..magnitudes.len())
+        .map(|t| {
+            (0..bins)
+                .map(|k| {
+                    values.clear();
+                    let frames = t.saturating_sub(along_time)..(t + along_time + 1).min(magnitudes.len());
+                    values.extend(frames.map(|frame| magnitudes[frame][k]));
+                    let harmonic = median(&mut values).powf(hpss.power);
+
+                    values.clear();
+                    let around = k.saturating_sub(along_frequency)..(k + along_frequency + 1).min(bins);
+                    values.extend_from_slice(&magnitudes[t][around]);
+                    let percussive = median(&mut values).powf(hpss.power);
+
+                    if harmonic + percussive > 0.0 { harmonic / (harmonic + percussive) } else { 0.5 }
+                })
+                .collect()
+        })
+        .collect();
+
+    let split = |share: fn(f32) -> f32| -> Vec<f32> {
+        let masked: Vec<_> = spectra.iter()
+            .zip(&masks)
+            .map(|(spectrum, mask)| {
+                spectrum.iter()
+                    .enumerate()
+                    .map(|(k, c)| c * share(mask[k.min(frame_size - k)]))
+                    .collect()
+            })
+            .collect();
+        istft(&masked, frame_size, hop, samples.len())
+    };
+
+    Separation { harmonic: split(|mask| mask), percussive: split(|mask| 1.0 - mask) }
+}
+
+// middle value of a non-empty slice, reordering it
+fn median(values: &mut [f32]) -> f32 {
+    let middle = values.len() / 2;
+    *values.select_nth_unstable_by(middle, f32::total_cmp).1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recognizer::{ChordRecognizer, Config};
+    use crate::synth::{ChordSpec, Synth};
+
+    // two seconds of a C major chord with a loud noise burst every quarter of a second
+    fn chord_with_drums() -> (Vec<f32>, Vec<f32>) {
+        let chord = Synth::default().render(&ChordSpec { duration: 2.0, ..ChordSpec::from_harte("C:maj").unwrap() });
+        let drums: Vec<f32> = (0..chord.len())
+            .map(|t| {
+                let since_hit = (t % 11025) as f32 / 44100.0;
+                let noise = ((t as f32 * 12.9898).sin() * 43758.547).fract() * 2.0 - 1.0;
+                2.0 * noise * (-since_hit / 0.02).exp()
+            })
+            .collect();
+        let mix = chord.iter().zip(&drums).map(|(c, d)| c + d).collect();
+        (mix, drums)
+    }
+
+    #[test]
+    fn splits_chord_from_drums() {
+        let (mix, drums) = chord_with_drums();
+        let Separation { harmonic, percussive } = separate(&mix, &Hpss::default());
+
+        for (m, (h, p)) in mix.iter().zip(harmonic.iter().zip(&percussive)) {
+            assert!((m - h - p).abs() < 1e-4);
+        }
+
+        let energy = |signal: &[f32]| signal.iter().map(|v| v * v).sum::<f32>();
+        let leaked: Vec<f32> = percussive.iter().zip(&drums).map(|(p, d)| p - d).collect();
+        assert!(energy(&leaked) < 0.05 * energy(&drums));
+
+        // the drums alone are enough to throw the chord off
+        let plain = ChordRecognizer::new(Config::default()).recognize(&mix, 44100).unwrap();
+        assert_ne!(plain.harte(), "C:maj");
+
+        let separated = ChordRecognizer::new(Config { hpss: Some(Hpss::default()), ..Config::default() });
+        let result = separated.recognize(&mix, 44100).unwrap();
+        assert_eq!((result.root, result.quality.map(|q| q.harte)), (Some(0), Some("maj")));
+    }
+}
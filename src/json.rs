@@ -0,0 +1,323 @@
+//! A small JSON value type for machine-readable output, so the crate doesn't need a serializer.
+
+use std::fmt;
+
+use crate::analysis::{Cadence, Numeral};
+use crate::key::{Key, KeyEstimate, KeyScore, KeySegment, Modulation};
+use crate::recognizer::{Candidate, ChordResult, Segment};
+use crate::spelling::Spelling;
+
+/// Version of the JSON documents produced by the CLI. Bumped whenever a field is renamed,
+/// removed or changes meaning; new fields may be added without a bump.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// How many pitch classes are listed under `top_pitch_classes`.
+pub const TOP_PITCH_CLASSES: usize = 3;
+
+/// A JSON value. Objects keep their keys in insertion order so output is stable.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// An object from `(key, value)` pairs, in order.
+    pub fn object<K: Into<String>>(fields: impl IntoIterator<Item = (K, Json)>) -> Json {
+        Json::Object(fields.into_iter().map(|(k, v)| (k.into(), v)).collect())
+    }
+
+    /// Appends a field to an object, any other value is left as it is.
+    pub fn push(&mut self, key: impl Into<String>, value: Json) {
+        if let Json::Object(fields) = self {
+            fields.push((key.into(), value));
+        }
+    }
+
+    // writes the value, indenting nested objects when `indent` is set
+    fn write(&self, f: &mut fmt::Formatter, indent: Option<usize>) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            // JSON has no NaN or infinity
+            Json::Number(n) if !n.is_finite() => write!(f, "null"),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(s) => write_string(f, s),
+            // arrays stay on one line, they only ever hold short scalars or get long anyway
+            Json::Array(items) if items.iter().all(|item| !item.is_container()) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    item.write(f, None)?;
+                }
+                write!(f, "]")
+            }
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    newline(f, indent.map(|n| n + 1))?;
+                    item.write(f, indent.map(|n| n + 1))?;
+                }
+                if !items.is_empty() {
+                    newline(f, indent)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    newline(f, indent.map(|n| n + 1))?;
+                    write_string(f, key)?;
+                    write!(f, ":")?;
+                    if indent.is_some() {
+                        write!(f, " ")?;
+                    }
+                    value.write(f, indent.map(|n| n + 1))?;
+                }
+                if !fields.is_empty() {
+                    newline(f, indent)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+
+    fn is_container(&self) -> bool {
+        matches!(self, Json::Array(_) | Json::Object(_))
+    }
+}
+
+/// `{}` prints compact JSON, `{:#}` pretty prints it with two space indentation.
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let indent = if f.alternate() { Some(0) } else { None };
+        self.write(f, indent)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Json {
+        Json::Bool(b)
+    }
+}
+
+impl From<f32> for Json {
+    fn from(n: f32) -> Json {
+        // going through the shortest f32 representation keeps 0.1 from turning into 0.10000000149
+        Json::Number(n.to_string().parse().unwrap_or(f64::NAN))
+    }
+}
+
+impl From<usize> for Json {
+    fn from(n: usize) -> Json {
+        Json::Number(n as f64)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Json {
+        Json::String(s.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Json {
+        Json::String(s)
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Json {
+        value.map_or(Json::Null, Into::into)
+    }
+}
+
+impl<T: Into<Json>> From<Vec<T>> for Json {
+    fn from(items: Vec<T>) -> Json {
+        Json::Array(items.into_iter().map(Into::into).collect())
+    }
+}
+
+impl From<&ChordResult> for Json {
+    fn from(result: &ChordResult) -> Json {
+        chord(result, Spelling::Sharps)
+    }
+}
+
+impl From<&Candidate> for Json {
+    fn from(candidate: &Candidate) -> Json {
+        self::candidate(candidate, Spelling::Sharps)
+    }
+}
+
+impl From<&Segment> for Json {
+    fn from(segment: &Segment) -> Json {
+        self::segment(segment, Spelling::Sharps)
+    }
+}
+
+impl From<Key> for Json {
+    fn from(key: Key) -> Json {
+        Json::object([
+            ("name", key.name().into()),
+            ("tonic", Spelling::Key(key).note(key.tonic).into()),
+            ("mode", key.mode.name().into()),
+        ])
+    }
+}
+
+impl From<&KeyScore> for Json {
+    fn from(key: &KeyScore) -> Json {
+        let mut json = Json::from(key.key);
+        json.push("score", key.score.into());
+        json
+    }
+}
+
+impl From<&KeyEstimate> for Json {
+    fn from(estimate: &KeyEstimate) -> Json {
+        let mut json = Json::from(&estimate.best);
+        json.push("relative", (&estimate.relative).into());
+        json.push("parallel", (&estimate.parallel).into());
+        json
+    }
+}
+
+impl From<&KeySegment> for Json {
+    fn from(segment: &KeySegment) -> Json {
+        let mut key = Json::from(segment.key);
+        key.push("score", segment.score.into());
+        Json::object([("start", segment.start.into()), ("end", segment.end.into()), ("key", key)])
+    }
+}
+
+impl From<&Modulation> for Json {
+    fn from(modulation: &Modulation) -> Json {
+        Json::object([
+            ("time", modulation.time.into()),
+            ("from", modulation.from.into()),
+            ("to", modulation.to.into()),
+        ])
+    }
+}
+
+impl From<&Numeral> for Json {
+    fn from(numeral: &Numeral) -> Json {
+        Json::object([("text", numeral.text.as_str().into()), ("role", numeral.role.name().into())])
+    }
+}
+
+impl From<&Cadence> for Json {
+    fn from(cadence: &Cadence) -> Json {
+        Json::object([
+            ("kind", cadence.kind.name().into()),
+            ("time", cadence.time.into()),
+            ("segment", cadence.segment.into()),
+        ])
+    }
+}
+
+/// A recognized chord, with notes named by `spelling`.
+pub fn chord(result: &ChordResult, spelling: Spelling) -> Json {
+    let chord = result.root.zip(result.quality);
+    let bass = chord.zip(result.bass).map(|((root, quality), bass)| spelling.bass(root, quality, bass));
+    let top_pitch_classes: Vec<String> =
+        result.top_notes(TOP_PITCH_CLASSES).into_iter().map(|pc| spelling.pitch_class(pc)).collect();
+
+    Json::object([
+        ("label", result.spelled_harte(spelling).into()),
+        ("name", result.spelled_name(spelling).into()),
+        ("root", result.root.map(|pc| spelling.note(pc)).into()),
+        ("quality", result.quality.map(|q| q.harte).into()),
+        ("bass", bass.into()),
+        ("tones", result.tones(spelling).into()),
+        ("score", result.score.into()),
+        ("probability", result.probability.into()),
+        ("candidates", Json::Array(result.candidates.iter().map(|c| candidate(c, spelling)).collect())),
+        ("pitch_energy", result.chroma.to_vec().into()),
+        ("top_pitch_classes", top_pitch_classes.into()),
+    ])
+}
+
+/// A candidate chord, with its root named by `spelling`.
+pub fn candidate(candidate: &Candidate, spelling: Spelling) -> Json {
+    Json::object([
+        ("label", candidate.spelled_harte(spelling).into()),
+        ("name", candidate.spelled_name(spelling).into()),
+        ("score", candidate.score.into()),
+        ("probability", candidate.probability.into()),
+    ])
+}
+
+/// A chord segment, with notes named by `spelling`.
+pub fn segment(segment: &Segment, spelling: Spelling) -> Json {
+    Json::object([
+        ("start", segment.start.into()),
+        ("end", segment.end.into()),
+        ("chord", chord(&segment.chord, spelling)),
+    ])
+}
+
+fn newline(f: &mut fmt::Formatter, indent: Option<usize>) -> fmt::Result {
+    match indent {
+        Some(n) => write!(f, "\n{:width$}", "", width = 2 * n),
+        None => Ok(()),
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_strings() {
+        let s = Json::from("say \"hi\" \\ tab\there\r\nbell\u{7} é");
+        assert_eq!(s.to_string(), r#""say \"hi\" \\ tab\there\r\nbell\u0007 é""#);
+
+        // keys go through the same escaping
+        let object = Json::object([("a\"b", Json::Null)]);
+        assert_eq!(object.to_string(), r#"{"a\"b":null}"#);
+    }
+
+    #[test]
+    fn non_finite_numbers_are_null() {
+        let numbers: Json = vec![Json::from(f32::NAN), Json::from(f32::INFINITY), Json::Number(f64::NEG_INFINITY)].into();
+        assert_eq!(numbers.to_string(), "[null, null, null]");
+        assert_eq!(Json::from(0.1f32).to_string(), "0.1");
+    }
+
+    #[test]
+    fn pretty_prints_nested_values() {
+        let value = Json::object([("a", Json::from(vec![1usize, 2])), ("b", Json::object([("c", Json::Bool(true))]))]);
+        assert_eq!(value.to_string(), r#"{"a":[1, 2],"b":{"c":true}}"#);
+        assert_eq!(format!("{:#}", value), "{\n  \"a\": [1, 2],\n  \"b\": {\n    \"c\": true\n  }\n}");
+    }
+}
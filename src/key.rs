@@ -0,0 +1,270 @@
+//! Key estimation by correlating pitch-class energy with major and minor key profiles, after
+//! Krumhansl and Schmuckler.
+
+use crate::chords::roll_template;
+use crate::harte::parse_note;
+use crate::similarity::{Pearson, Similarity};
+use crate::spelling::Spelling;
+
+// probe-tone ratings of Krumhansl and Kessler (1982), tonic first
+const KRUMHANSL_MAJOR: [f32; 12] = [6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88];
+const KRUMHANSL_MINOR: [f32; 12] = [6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17];
+
+// Temperley's revision of the Krumhansl-Kessler profiles (1999)
+const TEMPERLEY_MAJOR: [f32; 12] = [5.0, 2.0, 3.5, 2.0, 4.5, 4.0, 2.0, 4.5, 2.0, 3.5, 1.5, 4.0];
+const TEMPERLEY_MINOR: [f32; 12] = [5.0, 2.0, 3.5, 4.5, 2.0, 4.0, 2.0, 4.5, 3.5, 2.0, 1.5, 4.0];
+
+/// Weights of the twelve pitch classes in a major and a minor key, the tonic first.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Profile {
+    KrumhanslKessler,
+    Temperley,
+    Custom { major: [f32; 12], minor: [f32; 12] },
+}
+
+impl Profile {
+    /// Parses `krumhansl` or `temperley`.
+    pub fn parse(name: &str) -> Option<Profile> {
+        match name {
+            "krumhansl" => Some(Profile::KrumhanslKessler),
+            "temperley" => Some(Profile::Temperley),
+            _ => None,
+        }
+    }
+
+    /// The profile of a key in `mode` on C.
+    pub fn weights(&self, mode: Mode) -> [f32; 12] {
+        match (self, mode) {
+            (Profile::KrumhanslKessler, Mode::Major) => KRUMHANSL_MAJOR,
+            (Profile::KrumhanslKessler, Mode::Minor) => KRUMHANSL_MINOR,
+            (Profile::Temperley, Mode::Major) => TEMPERLEY_MAJOR,
+            (Profile::Temperley, Mode::Minor) => TEMPERLEY_MINOR,
+            (Profile::Custom { major, .. }, Mode::Major) => *major,
+            (Profile::Custom { minor, .. }, Mode::Minor) => *minor,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Major,
+    Minor,
+}
+
+impl Mode {
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Major => "major",
+            Mode::Minor => "minor",
+        }
+    }
+}
+
+/// A major or minor key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Key {
+    /// Pitch class of the tonic, 0 being C.
+    pub tonic: usize,
+    pub mode: Mode,
+}
+
+impl Key {
+    /// Every key, the major keys from C up followed by the minor keys from C up.
+    pub fn all() -> impl Iterator<Item = Key> {
+        [Mode::Major, Mode::Minor].into_iter()
+            .flat_map(|mode| (0..12).map(move |tonic| Key { tonic, mode }))
+    }
+
+    /// Parses a tonic and a mode, e.g. `Eb major` or `F# minor`.
+    pub fn parse(name: &str) -> Option<Key> {
+        let (tonic, mode) = name.trim().split_once(' ')?;
+        let (tonic, "") = parse_note(tonic)? else {
+            return None;
+        };
+        let mode = match mode.trim() {
+            "major" => Mode::Major,
+            "minor" => Mode::Minor,
+            _ => return None,
+        };
+        Some(Key { tonic, mode })
+    }
+
+    /// Human readable, e.g. "Eb major", the tonic spelled the way the key usually is.
+    pub fn name(&self) -> String {
+        format!("{} {}", Spelling::Key(*self).note(self.tonic), self.mode.name())
+    }
+
+    /// The key sharing this one's key signature, A minor for C major and the other way round.
+    pub fn relative(&self) -> Key {
+        match self.mode {
+            Mode::Major => Key { tonic: (self.tonic + 9) % 12, mode: Mode::Minor },
+            Mode::Minor => Key { tonic: (self.tonic + 3) % 12, mode: Mode::Major },
+        }
+    }
+
+    /// The key on the same tonic in the other mode, C minor for C major.
+    pub fn parallel(&self) -> Key {
+        let mode = match self.mode {
+            Mode::Major => Mode::Minor,
+            Mode::Minor => Mode::Major,
+        };
+        Key { tonic: self.tonic, mode }
+    }
+
+    // position in the order of Key::all
+    fn index(&self) -> usize {
+        match self.mode {
+            Mode::Major => self.tonic,
+            Mode::Minor => 12 + self.tonic,
+        }
+    }
+}
+
+/// A key along with how well the pitch-class energy correlates with its profile, from -1 to 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeyScore {
+    pub key: Key,
+    pub score: f32,
+}
+
+/// The best matching key and the two keys it is most easily mistaken for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeyEstimate {
+    pub best: KeyScore,
+    pub relative: KeyScore,
+    pub parallel: KeyScore,
+}
+
+/// A stretch of the recording in one key, times in seconds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeySegment {
+    pub start: f32,
+    pub end: f32,
+    pub key: Key,
+    /// Correlation with the key profile, averaged over the frames of the segment.
+    pub score: f32,
+}
+
+/// A change of key between two neighbouring key segments.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Modulation {
+    /// When the new key takes over, in seconds.
+    pub time: f32,
+    pub from: Key,
+    pub to: Key,
+}
+
+/// The modulations between consecutive `segments`.
+pub fn modulations(segments: &[KeySegment]) -> Vec<Modulation> {
+    segments.windows(2)
+        .filter(|pair| pair[0].key != pair[1].key)
+        .map(|pair| Modulation { time: pair[1].start, from: pair[0].key, to: pair[1].key })
+        .collect()
+}
+
+/// The key in effect at `time`, taking the first or last key outside the segments.
+pub fn key_at(segments: &[KeySegment], time: f32) -> Option<Key> {
+    let segment = segments.iter().find(|segment| time < segment.end).or(segments.last())?;
+    Some(segment.key)
+}
+
+/// Correlation of `chroma` with the profile of every key, in the order of [`Key::all`].
+pub fn key_scores(chroma: &[f32; 12], profile: &Profile) -> Vec<KeyScore> {
+    Key::all()
+        .map(|key| {
+            let weights = roll_template(&profile.weights(key.mode), key.tonic);
+            KeyScore { key, score: Pearson.score(chroma, &weights) }
+        })
+        .collect()
+}
+
+/// The key whose profile correlates best with `chroma`.
+pub fn estimate_key(chroma: &[f32; 12], profile: &Profile) -> KeyEstimate {
+    let scores = key_scores(chroma, profile);
+
+    // the first key reaching the highest score wins, as with chords
+    let best = scores.iter().fold(scores[0], |best, s| if s.score > best.score { *s } else { best });
+
+    KeyEstimate {
+        best,
+        relative: scores[best.key.relative().index()],
+        parallel: scores[best.key.parallel().index()],
+    }
+}
+
+// transition weights between keys in the order of Key::all, favouring keys close on the circle
+// of fifths. Relative keys share a key signature but are still a change of mode
+pub(crate) fn transition_weights() -> Vec<Vec<f32>> {
+    let signature = |key: Key| {
+        let major = match key.mode {
+            Mode::Major => key.tonic,
+            Mode::Minor => key.relative().tonic,
+        };
+        (major * 7) % 12
+    };
+
+    Key::all()
+        .map(|from| {
+            Key::all()
+                .map(|to| {
+                    let steps = (12 + signature(from) - signature(to)) % 12;
+                    let distance = steps.min(12 - steps) as f32;
+                    let mode_change = if from.mode != to.mode { 1.0 } else { 0.0 };
+                    (-(distance + mode_change)).exp()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recognizer::{ChordRecognizer, Config};
+    use crate::synth::render_chords;
+
+    fn key_of(progression: &[&str], profile: Profile) -> KeyEstimate {
+        let chords: Vec<(&str, f32)> = progression.iter().map(|&label| (label, 0.5)).collect();
+        let (samples, _) = render_chords(&chords);
+
+        let recognizer = ChordRecognizer::new(Config { key_profile: profile, ..Config::default() });
+        recognizer.key(&samples, 44100).unwrap()
+    }
+
+    // twelve seconds of C major, then twelve of G major. G:7 and C:maj from 9 s on belong to both
+    // keys, so the change can come anywhere from there
+    #[test]
+    fn tracks_modulation() {
+        let progression = [
+            "C:maj", "F:maj", "G:7", "C:maj", "A:min", "D:min", "G:7", "C:maj",
+            "G:maj", "C:maj", "D:7", "G:maj", "E:min", "A:min", "D:7", "G:maj",
+        ];
+        let chords: Vec<(&str, f32)> = progression.iter().map(|&label| (label, 1.5)).collect();
+        let (samples, _) = render_chords(&chords);
+
+        let recognizer = ChordRecognizer::new(Config::default());
+        let segments = recognizer.key_segments(&samples, 44100).unwrap();
+        let keys: Vec<String> = segments.iter().map(|segment| segment.key.name()).collect();
+        assert_eq!(keys, ["C major", "G major"]);
+
+        let [modulation] = modulations(&segments)[..] else {
+            panic!("expected a single modulation, got {:?}", segments);
+        };
+        assert!((9.0..13.5).contains(&modulation.time), "modulation at {}", modulation.time);
+    }
+
+    // cadences in C major and A minor share their pitch classes but not their tonal centre
+    #[test]
+    fn finds_key_of_cadences() {
+        for profile in [Profile::KrumhanslKessler, Profile::Temperley] {
+            let c_major = key_of(&["C:maj", "F:maj", "G:7", "C:maj"], profile.clone());
+            assert_eq!(c_major.best.key.name(), "C major");
+            assert_eq!(c_major.relative.key.name(), "A minor");
+            assert_eq!(c_major.parallel.key.name(), "C minor");
+
+            let a_minor = key_of(&["A:min", "D:min", "E:7", "A:min"], profile);
+            assert_eq!(a_minor.best.key.name(), "A minor");
+            assert!(a_minor.best.score > a_minor.relative.score);
+        }
+    }
+}
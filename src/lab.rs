@@ -0,0 +1,101 @@
+//! MIREX / Isophonics `.lab` chord annotations: one `start end label` line per segment, times in
+//! seconds and labels in Harte syntax.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use crate::error::ChordError;
+use crate::recognizer::Segment;
+
+/// One line of a `.lab` file: a chord label over a stretch of time in seconds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Annotation {
+    pub start: f32,
+    pub end: f32,
+    pub label: String,
+}
+
+impl From<&Segment> for Annotation {
+    fn from(segment: &Segment) -> Annotation {
+        Annotation { start: segment.start, end: segment.end, label: segment.chord.harte() }
+    }
+}
+
+/// Reads `.lab` lines, separated by spaces or tabs. Blank lines are skipped.
+pub fn read_lab<R: BufRead>(reader: R) -> Result<Vec<Annotation>, ChordError> {
+    let mut annotations = Vec::new();
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.is_empty() {
+            continue;
+        }
+
+        let invalid = || ChordError::InvalidAnnotation(format!("line {}: {:?}", i + 1, line));
+        let [start, end, label] = fields.as_slice() else {
+            return Err(invalid());
+        };
+        let start: f32 = start.parse().map_err(|_| invalid())?;
+        let end: f32 = end.parse().map_err(|_| invalid())?;
+        if !start.is_finite() || !end.is_finite() || end < start {
+            return Err(invalid());
+        }
+
+        annotations.push(Annotation { start, end, label: label.to_string() });
+    }
+
+    Ok(annotations)
+}
+
+/// Reads the `.lab` file at `path`.
+pub fn load_lab(path: impl AsRef<Path>) -> Result<Vec<Annotation>, ChordError> {
+    read_lab(BufReader::new(File::open(path)?))
+}
+
+/// Writes `.lab` lines, e.g. `0.000000 2.612267 A:min`. Recognized segments convert into
+/// annotations with [`Annotation::from`], labeled `N` where no chord was found.
+pub fn write_lab<W: Write>(mut writer: W, annotations: &[Annotation]) -> Result<(), ChordError> {
+    let mut write = || -> io::Result<()> {
+        for annotation in annotations {
+            writeln!(writer, "{:.6} {:.6} {}", annotation.start, annotation.end, annotation.label)?;
+        }
+        writer.flush()
+    };
+    write().map_err(ChordError::Write)
+}
+
+/// Writes a `.lab` file at `path`, replacing it if it exists.
+pub fn save_lab(path: impl AsRef<Path>, annotations: &[Annotation]) -> Result<(), ChordError> {
+    write_lab(BufWriter::new(File::create(path).map_err(ChordError::Write)?), annotations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let annotations = [
+            Annotation { start: 0.0, end: 2.612267, label: "A:min".to_string() },
+            Annotation { start: 2.612267, end: 3.1, label: "N".to_string() },
+            Annotation { start: 3.1, end: 5.25, label: "D:maj/5".to_string() },
+            Annotation { start: 5.25, end: 7.0, label: "Bb:min7/b3".to_string() },
+            Annotation { start: 7.0, end: 61.5, label: "G:7(b9)".to_string() },
+        ];
+
+        let mut bytes = Vec::new();
+        write_lab(&mut bytes, &annotations).unwrap();
+        let text = String::from_utf8(bytes).unwrap();
+        assert_eq!(text.lines().next(), Some("0.000000 2.612267 A:min"));
+        assert_eq!(text.lines().last(), Some("7.000000 61.500000 G:7(b9)"));
+
+        let read = read_lab(text.as_bytes()).unwrap();
+        assert_eq!(read.len(), annotations.len());
+        for (read, written) in read.iter().zip(&annotations) {
+            assert!((read.start - written.start).abs() < 1e-5 && (read.end - written.end).abs() < 1e-5);
+            assert_eq!(read.label, written.label);
+        }
+    }
+}
@@ -0,0 +1,44 @@
+//! Chord recognition from WAV audio.
+//!
+//! Build a [`ChordRecognizer`] from a [`Config`] and feed it a path, a reader or a slice of mono
+//! samples:
+//!
+//! ```no_run
+//! use chord::{ChordRecognizer, Config};
+//!
+//! let recognizer = ChordRecognizer::new(Config::default());
+//! for result in recognizer.recognize_path("samples/amchord.wav")? {
+//!     println!("{} ({})", result.name(), result.harte());
+//! }
+//! # Ok::<(), chord::ChordError>(())
+//! ```
+
+pub mod analysis;
+mod audio;
+pub mod beat;
+mod chords;
+mod chroma;
+mod cqt;
+mod error;
+pub mod evaluation;
+mod harte;
+mod hmm;
+pub mod hpss;
+pub mod json;
+pub mod key;
+pub mod lab;
+pub mod onset;
+mod recognizer;
+pub mod similarity;
+pub mod spelling;
+mod stft;
+pub mod synth;
+pub mod tuning;
+mod vocabulary;
+
+pub use audio::{ChannelMode, open_wav, read_wav, write_wav};
+pub use chords::NOTE_NAMES;
+pub use chroma::FrontEnd;
+pub use error::ChordError;
+pub use recognizer::{Candidate, ChordRecognizer, ChordResult, Config, Gate, KeyTracking, Segment, Smoothing};
+pub use vocabulary::{QUALITIES, Quality, Vocabulary};
@@ -1,170 +1,967 @@
 use std::env;
-use std::f32::consts::PI;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use chord::analysis::{Analysis, analyze_each};
+use chord::beat::{Beats, Grid};
+use chord::evaluation::{Evaluation, Level, evaluate};
+use chord::hpss::{self, Hpss};
+use chord::json::{self, Json};
+use chord::key::{self, Key, KeyEstimate, KeySegment, Profile};
+use chord::lab::{Annotation, load_lab, save_lab};
+use chord::onset::OnsetMethod;
+use chord::similarity::Metric;
+use chord::spelling::Spelling;
+use chord::synth::{ChordSpec, Step, Synth, Timbre};
+use chord::{
+    ChannelMode, ChordError, ChordRecognizer, ChordResult, Config, FrontEnd, Gate, Segment, Smoothing, Vocabulary,
+    open_wav, tuning, write_wav,
+};
+
+// exit status of a command line that can't be made sense of, EX_USAGE of sysexits.h
+const USAGE_ERROR: i32 = 64;
+
+// every kind of failure gets its own exit status so scripts can tell them apart
+fn exit_code(e: &ChordError) -> i32 {
+    match e {
+        ChordError::Io(_) => 2,
+        ChordError::Decode(_) => 3,
+        ChordError::UnsupportedFormat(_) => 4,
+        ChordError::InvalidChannel { .. } => 5,
+        ChordError::EmptyInput => 6,
+        ChordError::SilentInput => 7,
+        ChordError::NonFinite => 8,
+        ChordError::InvalidAnnotation(_) => 9,
+        ChordError::InvalidReference(_) => 10,
+        ChordError::Write(_) => 11,
+    }
+}
 
-use hound;
-use rustfft::{FftPlanner, num_complex::Complex};
+fn fail(e: ChordError) -> ! {
+    eprintln!("Error: {}", e);
+    process::exit(exit_code(&e));
+}
 
-const NOTE_NAMES: [&str; 12] = [
-    "C","C#","D","D#","E","F",
-    "F#","G","G#","A","A#","B"
-];
+fn usage(message: impl fmt::Display) -> ! {
+    eprintln!("{}", message);
+    process::exit(USAGE_ERROR);
+}
 
-// index 0 - root, index 4 - major third, index 7 - perfect fifth
-// currently corresponds to C major
-const MAJOR_TEMPLATE: [f32; 12] =
-    [1.0,0.0,0.0,0.0,1.0,0.0,0.0,1.0,0.0,0.0,0.0,0.0];
+fn main() {
+    let args: Vec<String> = env::args().collect();
 
-// index 0 - root, index 3 - minor third, index 7 - perfect fifth
-const MINOR_TEMPLATE: [f32; 12] =
-    [1.0,0.0,0.0,1.0,0.0,0.0,0.0,1.0,0.0,0.0,0.0,0.0];
+    if args.get(1).is_some_and(|command| command == "synth") {
+        run_synth(&args[2..]);
+        return;
+    }
 
-// converts note to pitch (eg. A3 -> A, D2 -> D etc.)
-fn freq_to_pitch(freq: f32) -> usize {
-    // TODO log2 as a lookup table for performance
-    let midi = 69.0 + 12.0 * (freq / 440.0).log2();
-    // round to nearest semitone
-    let note_number = midi.round() as i32;
-    // convert to pitch class (0–11)
-    let mut pitch_class = note_number % 12;
+    let mut config = Config::default();
+    let mut positional: Vec<String> = Vec::new();
+    let mut segmented = false;
+    let mut json = false;
+    let mut lab: Option<String> = None;
+    let mut key = false;
+    let mut roman = false;
+    let mut modulations = false;
+    let mut given_key: Option<Key> = None;
+    let mut show_onsets = false;
+    let mut onsets_file: Option<String> = None;
+    let mut snap = false;
+    let mut snap_tolerance = 0.2;
+    let mut show_beats = false;
+    let mut beats_file: Option<String> = None;
+    let mut grid: Option<Grid> = None;
+    let mut harmonic_wav: Option<String> = None;
+    let mut percussive_wav: Option<String> = None;
+    let mut smooth = false;
+    let mut fifths = false;
+    let mut self_prob = 0.9;
+    let mut constant_q = false;
+    let mut bins_per_octave = 36;
+    let mut cqt_min = 65.0;
+    let mut cqt_max = 1500.0;
+    let mut gate = Gate::default();
+    let mut gate_tuned = false;
+    let mut no_gate = false;
+
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--segments" => segmented = true,
+            "--smooth" => smooth = true,
+            "--fifths" => fifths = true,
+            "--split-channels" => config.channel_mode = ChannelMode::Separate,
+            "--channel" => {
+                let Some(value) = args.get(i + 1).and_then(|v| v.parse::<usize>().ok()) else {
+                    usage("--channel expects a channel index, 0 being the left channel");
+                };
+                config.channel_mode = ChannelMode::Channel(value);
+                i += 1;
+            }
+            "--format" => {
+                match args.get(i + 1).map(String::as_str) {
+                    Some("text") => json = false,
+                    Some("json") => json = true,
+                    _ => {
+                        usage("--format expects text or json");
+                    }
+                }
+                i += 1;
+            }
+            "--lab" => {
+                let Some(value) = args.get(i + 1) else {
+                    usage("--lab expects the path of the .lab file to write");
+                };
+                lab = Some(value.clone());
+                i += 1;
+            }
+            "--key" => key = true,
+            "--onsets" => show_onsets = true,
+            "--onsets-file" => {
+                let Some(value) = args.get(i + 1) else {
+                    usage("--onsets-file expects the path of the file to write onset times to");
+                };
+                onsets_file = Some(value.clone());
+                i += 1;
+            }
+            "--onset-method" => {
+                let Some(value) = args.get(i + 1).and_then(|v| OnsetMethod::parse(v)) else {
+                    usage("--onset-method expects one of flux, hfc, complex");
+                };
+                config.onsets.method = value;
+                i += 1;
+            }
+            "--beats" => show_beats = true,
+            "--beats-file" => {
+                let Some(value) = args.get(i + 1) else {
+                    usage("--beats-file expects the path of the file to write beat times to");
+                };
+                beats_file = Some(value.clone());
+                i += 1;
+            }
+            "--chords-per" => {
+                let Some(value) = args.get(i + 1).and_then(|v| Grid::parse(v)) else {
+                    usage("--chords-per expects beat or bar");
+                };
+                grid = Some(value);
+                segmented = true;
+                i += 1;
+            }
+            "--beats-per-bar" => {
+                let value = args.get(i + 1).and_then(|v| v.parse::<usize>().ok());
+                let Some(value) = value.filter(|&v| v > 0) else {
+                    usage("--beats-per-bar expects a positive number of beats");
+                };
+                config.beats.beats_per_bar = value;
+                i += 1;
+            }
+            "--snap-onsets" => {
+                snap = true;
+                segmented = true;
+            }
+            "--snap-tolerance" => {
+                let value = args.get(i + 1).and_then(|v| v.parse::<f32>().ok());
+                let Some(value) = value.filter(|&v| v >= 0.0) else {
+                    usage("--snap-tolerance expects a time in seconds");
+                };
+                snap_tolerance = value;
+                i += 1;
+            }
+            "--roman" => {
+                roman = true;
+                segmented = true;
+            }
+            "--modulations" => {
+                modulations = true;
+                segmented = true;
+            }
+            "--key-window" => {
+                let value = args.get(i + 1).and_then(|v| v.parse::<f32>().ok());
+                let Some(value) = value.filter(|&v| v > 0.0) else {
+                    usage("--key-window expects a window length in seconds");
+                };
+                config.key_tracking.window = value;
+                i += 1;
+            }
+            "--in-key" => {
+                let Some(value) = args.get(i + 1).and_then(|v| Key::parse(v)) else {
+                    usage("--in-key expects a key such as \"Eb major\" or \"F# minor\"");
+                };
+                given_key = Some(value);
+                i += 1;
+            }
+            "--key-profile" => {
+                let Some(value) = args.get(i + 1).and_then(|v| parse_profile(v)) else {
+                    usage("--key-profile expects krumhansl, temperley, or 24 comma separated weights, \
+                              the major profile from its tonic up followed by the minor one");
+                };
+                config.key_profile = value;
+                i += 1;
+            }
+            "--similarity" => {
+                let Some(value) = args.get(i + 1).and_then(|v| Metric::parse(v)) else {
+                    usage("--similarity expects one of dot, cosine, pearson, euclidean, kl");
+                };
+                config.metric = value;
+                i += 1;
+            }
+            "--vocabulary" => {
+                let Some(value) = args.get(i + 1).and_then(|v| Vocabulary::parse(v)) else {
+                    usage("--vocabulary expects one of majmin, triads, sevenths, full");
+                };
+                config.vocabulary = value;
+                i += 1;
+            }
+            "--self-prob" => {
+                let value = args.get(i + 1).and_then(|v| v.parse::<f32>().ok());
+                let Some(value) = value.filter(|v| (0.0..1.0).contains(v)) else {
+                    usage("--self-prob expects a probability between 0 and 1");
+                };
+                self_prob = value;
+                i += 1;
+            }
+            "--cqt" => constant_q = true,
+            "--no-gate" => no_gate = true,
+            "--temperature" => {
+                let value = args.get(i + 1).and_then(|v| v.parse::<f32>().ok());
+                let Some(value) = value.filter(|&v| v > 0.0) else {
+                    usage("--temperature expects a positive number");
+                };
+                config.temperature = value;
+                i += 1;
+            }
+            "--top" => {
+                let value = args.get(i + 1).and_then(|v| v.parse::<usize>().ok());
+                let Some(value) = value.filter(|&n| n > 0) else {
+                    usage("--top expects a positive number of candidate chords to list");
+                };
+                config.candidates = value;
+                i += 1;
+            }
+            "--gate-db" => {
+                let Some(value) = args.get(i + 1).and_then(|v| v.parse::<f32>().ok()) else {
+                    usage("--gate-db expects a level in dBFS, e.g. -60");
+                };
+                gate.min_rms_db = value;
+                gate_tuned = true;
+                i += 1;
+            }
+            "--max-flatness" => {
+                let value = args.get(i + 1).and_then(|v| v.parse::<f32>().ok());
+                let Some(value) = value.filter(|v| (0.0..=1.0).contains(v)) else {
+                    usage("--max-flatness expects a spectral flatness between 0 and 1");
+                };
+                gate.max_flatness = value;
+                gate_tuned = true;
+                i += 1;
+            }
+            "--no-harmonics" => config.harmonics = false,
+            "--hpss" => config.hpss = Some(Hpss::default()),
+            "--harmonic-wav" | "--percussive-wav" => {
+                let Some(value) = args.get(i + 1) else {
+                    usage(format!("{} expects the path of the WAV file to write", args[i]));
+                };
+                if args[i] == "--harmonic-wav" {
+                    harmonic_wav = Some(value.clone());
+                } else {
+                    percussive_wav = Some(value.clone());
+                }
+                i += 1;
+            }
+            "--cqt-bins" => {
+                let value = args.get(i + 1).and_then(|v| v.parse::<usize>().ok());
+                let Some(value) = value.filter(|&v| v > 0) else {
+                    usage("--cqt-bins expects a positive number of bins per octave");
+                };
+                bins_per_octave = value;
+                i += 1;
+            }
+            "--reference-hz" => {
+                let value = args.get(i + 1).and_then(|v| v.parse::<f32>().ok());
+                let Some(value) = value.filter(|&v| v > 0.0) else {
+                    usage("--reference-hz expects the frequency of A4 in Hz");
+                };
+                config.reference_hz = Some(value);
+                i += 1;
+            }
+            "--cqt-min" | "--cqt-max" => {
+                let value = args.get(i + 1).and_then(|v| v.parse::<f32>().ok());
+                let Some(value) = value.filter(|&v| v > 0.0) else {
+                    usage(format!("{} expects a frequency in Hz", args[i]));
+                };
+                if args[i] == "--cqt-min" {
+                    cqt_min = value;
+                } else {
+                    cqt_max = value;
+                }
+                i += 1;
+            }
+            "--frame-size" | "--hop" => {
+                let value = args.get(i + 1).and_then(|v| v.parse::<usize>().ok());
+                let Some(value) = value.filter(|&v| v > 0) else {
+                    usage(format!("{} expects a positive number of samples", args[i]));
+                };
+                if args[i] == "--hop" {
+                    config.hop = value;
+                } else {
+                    config.frame_size = value;
+                }
+                i += 1;
+            }
+            other if other.starts_with("--") => usage(format!("Unknown option {}", other)),
+            other => positional.push(other.to_string()),
+        }
+        i += 1;
+    }
 
-    if pitch_class < 0 {
-        pitch_class += 12;
+    if no_gate && gate_tuned {
+        usage("--no-gate can't be combined with --gate-db or --max-flatness");
+    }
+    config.gate = (!no_gate).then_some(gate);
+    if (harmonic_wav.is_some() || percussive_wav.is_some()) && config.hpss.is_none() {
+        usage("--harmonic-wav and --percussive-wav write the parts --hpss separates, so they need --hpss");
+    }
+    if smooth || fifths {
+        config.smoothing = Some(Smoothing { self_prob, fifths });
+    }
+    if snap {
+        config.onset_snap = Some(snap_tolerance);
+    }
+    if constant_q {
+        config.front_end = FrontEnd::ConstantQ { bins_per_octave, min_freq: cqt_min, max_freq: cqt_max };
     }
 
-    pitch_class as usize
-}
+    if positional.first().is_some_and(|command| command == "evaluate") {
+        if config.channel_mode == ChannelMode::Separate || !(2..=3).contains(&positional.len()) {
+            usage("Usage: cargo run -- evaluate <audio.wav | audio dir> [<reference.lab | lab dir>] [options]");
+        }
+        run_evaluation(&ChordRecognizer::new(config), &positional[1], positional.get(2));
+        return;
+    }
 
-fn hann_window(n: usize) -> Vec<f32> {
-    let mut window = vec![0.0; n];
-    for i in 0..n {
-        window[i] = 0.5 - 0.5 * (2.0 * PI * i as f32 / n as f32).cos();
+    let [filename] = positional.as_slice() else {
+        usage("Usage: cargo run -- <filename.wav> [--segments] [--frame-size N] [--hop N] \
+                  [--smooth] [--self-prob P] [--fifths] [--channel N | --split-channels] \
+                  [--vocabulary majmin|triads|sevenths|full] [--similarity dot|cosine|pearson|euclidean|kl] \
+                  [--cqt] [--cqt-bins N] [--cqt-min HZ] [--cqt-max HZ] [--reference-hz HZ] \
+                  [--no-harmonics] [--hpss] [--harmonic-wav FILE] [--percussive-wav FILE] \
+                  [--gate-db DB] [--max-flatness F] [--no-gate] \
+                  [--temperature T] [--top N] [--key] [--key-profile krumhansl|temperley|WEIGHTS] [--roman] \
+                  [--in-key KEY] [--modulations] [--key-window SECS] [--onsets] [--onsets-file FILE] \
+                  [--onset-method flux|hfc|complex] [--snap-onsets] [--snap-tolerance SECS] \
+                  [--beats] [--beats-file FILE] [--chords-per beat|bar] [--beats-per-bar N] \
+                  [--format text|json] [--lab FILE]\n       \
+               cargo run -- evaluate <audio.wav | audio dir> [<reference.lab | lab dir>] [options]");
+    };
+
+    let (signals, sr) = open_wav(filename, config.channel_mode).unwrap_or_else(|e| fail(e));
+
+    // the separated parts are written as they are, before chroma is taken from the harmonic one
+    if harmonic_wav.is_some() || percussive_wav.is_some() {
+        let [samples] = signals.as_slice() else {
+            usage("--harmonic-wav and --percussive-wav need a single signal, pick one with --channel \
+                      instead of --split-channels");
+        };
+        let separation = hpss::separate(samples, &config.hpss.unwrap_or_default());
+        for (path, part) in [(&harmonic_wav, &separation.harmonic), (&percussive_wav, &separation.percussive)] {
+            if let Some(path) = path {
+                write_wav(path, part, sr).unwrap_or_else(|e| fail(e));
+            }
+        }
     }
-    window
-}
+    let separate = config.channel_mode == ChannelMode::Separate;
+    let recognizer = ChordRecognizer::new(config);
 
-// move the C major/minor template so that the root note corresponds to the chord played
-fn roll_template(template: &[f32;12], shift: usize) -> [f32;12] {
-    let mut out = [0.0;12];
+    let references: Vec<f32> = signals.iter()
+        .map(|samples| recognizer.reference_hz(samples, sr).unwrap_or_else(|e| fail(e)))
+        .collect();
 
-    for i in 0..12 {
-        out[(i + shift) % 12] = template[i];
+    // Roman numerals need a key, estimated unless one was given
+    let keys = Keys {
+        given: given_key,
+        estimates: (key || (roman && given_key.is_none())).then(|| {
+            signals.iter()
+                .map(|samples| recognizer.key(samples, sr).unwrap_or_else(|e| fail(e)))
+                .collect()
+        }),
+        local: modulations.then(|| {
+            signals.iter()
+                .map(|samples| recognizer.key_segments(samples, sr).unwrap_or_else(|e| fail(e)))
+                .collect()
+        }),
+    };
+
+    let timing = Timing {
+        onsets: (show_onsets || onsets_file.is_some()).then(|| {
+            signals.iter()
+                .map(|samples| recognizer.onsets(samples, sr).unwrap_or_else(|e| fail(e)))
+                .collect()
+        }),
+        beats: (show_beats || beats_file.is_some() || grid.is_some()).then(|| {
+            signals.iter()
+                .map(|samples| recognizer.beats(samples, sr).unwrap_or_else(|e| fail(e)))
+                .collect()
+        }),
+    };
+
+    if let (Some(path), Some(onsets)) = (&onsets_file, &timing.onsets) {
+        let [onsets] = onsets.as_slice() else {
+            usage("--onsets-file needs a single signal, pick one with --channel instead of --split-channels");
+        };
+        let lines: String = onsets.iter().map(|onset| format!("{:.6}\n", onset)).collect();
+        fs::write(path, lines).unwrap_or_else(|e| fail(ChordError::Write(e)));
     }
 
-    out
-}
+    if let (Some(path), Some(beats)) = (&beats_file, &timing.beats) {
+        let [beats] = beats.as_slice() else {
+            usage("--beats-file needs a single signal, pick one with --channel instead of --split-channels");
+        };
+        let lines: String = beats.times.iter().map(|beat| format!("{:.6}\n", beat)).collect();
+        fs::write(path, lines).unwrap_or_else(|e| fail(ChordError::Write(e)));
+    }
 
-fn dot(a: &[f32;12], b: &[f32;12]) -> f32 {
-    let mut sum = 0.0;
+    // a .lab file holds timed segments, so writing one implies segmenting
+    let segments: Option<Vec<Vec<Segment>>> = (segmented || lab.is_some()).then(|| {
+        signals.iter()
+            .enumerate()
+            .map(|(channel, samples)| match (grid, &timing.beats) {
+                (Some(grid), Some(beats)) => recognizer.beat_segments(samples, sr, &beats[channel], grid),
+                _ => recognizer.segments(samples, sr),
+            })
+            .map(|segments| segments.unwrap_or_else(|e| fail(e)))
+            .collect()
+    });
+
+    if let (Some(path), Some(segments)) = (&lab, &segments) {
+        let [segments] = segments.as_slice() else {
+            usage("--lab needs a single signal, pick one with --channel instead of --split-channels");
+        };
+        let annotations: Vec<Annotation> = segments.iter()
+            .map(|segment| Annotation {
+                label: segment.chord.spelled_harte(keys.spelling(0, segment)),
+                ..Annotation::from(segment)
+            })
+            .collect();
+        save_lab(path, &annotations).unwrap_or_else(|e| fail(e));
+    }
 
-    for i in 0..12 {
-        sum += a[i] * b[i];
+    let results: Option<Vec<ChordResult>> = segments.is_none().then(|| {
+        signals.iter()
+            .map(|samples| recognizer.recognize(samples, sr).unwrap_or_else(|e| fail(e)))
+            .collect()
+    });
+
+    if json {
+        let report = json_report(
+            recognizer.config().channel_mode,
+            filename,
+            &references,
+            &keys,
+            &timing,
+            segments.as_deref(),
+            results.as_deref(),
+        );
+        println!("{:#}", report);
+        return;
     }
 
-    sum
-}
+    for (channel, &reference) in references.iter().enumerate() {
+        if separate {
+            print!("Channel {} tuning: ", channel);
+        } else {
+            print!("Tuning: ");
+        }
+        println!("{:+.1} cents (A4 = {:.1} Hz)", tuning::cents_from(reference), reference);
+    }
 
-fn find_chord(filename: &str) -> String {
-    let mut reader = hound::WavReader::open(filename)
-        .expect("Failed to open WAV");
+    for channel in 0..signals.len() {
+        let label = if separate { format!("Channel {} key", channel) } else { "Key".to_string() };
+        if let Some(key) = keys.given {
+            println!("{}: {} (given)", label, key.name());
+        }
+        if let Some(estimate) = keys.estimates.as_ref().map(|estimates| &estimates[channel]) {
+            let label = if keys.given.is_some() { format!("{} estimate", label) } else { label };
+            println!(
+                "{}: {} ({:.2}), relative {} ({:.2}), parallel {} ({:.2})",
+                label,
+                estimate.best.key.name(),
+                estimate.best.score,
+                estimate.relative.key.name(),
+                estimate.relative.score,
+                estimate.parallel.key.name(),
+                estimate.parallel.score
+            );
+        }
+    }
 
-    let spec = reader.spec();
-    let sr = spec.sample_rate as usize;
+    for (channel, local) in keys.local.iter().flatten().enumerate() {
+        if separate {
+            println!("Channel {} keys:", channel);
+        } else {
+            println!("Keys:");
+        }
+        for segment in local {
+            println!("{:8.3} {:8.3}  {} ({:.2})", segment.start, segment.end, segment.key.name(), segment.score);
+        }
+        for modulation in key::modulations(local) {
+            println!("Modulation: {} to {} at {:.3}", modulation.from.name(), modulation.to.name(), modulation.time);
+        }
+    }
 
-    let samples: Vec<f32> = reader
-        .samples::<i16>()
-        .map(|s| s.unwrap() as f32 / i16::MAX as f32)
-        .collect();
+    for (channel, onsets) in timing.onsets.iter().flatten().enumerate() {
+        let times: Vec<String> = onsets.iter().map(|onset| format!("{:.3}", onset)).collect();
+        if separate {
+            println!("Channel {} onsets: {}", channel, times.join(" "));
+        } else {
+            println!("Onsets: {}", times.join(" "));
+        }
+    }
 
-    let n = samples.len();
-    if n == 0 {
-        return "Unknown".to_string();
+    for (channel, beats) in timing.beats.iter().flatten().enumerate() {
+        let times: Vec<String> = beats.times.iter().map(|beat| format!("{:.3}", beat)).collect();
+        if separate {
+            println!("Channel {} tempo: {:.1} BPM", channel, beats.bpm);
+            println!("Channel {} beats: {}", channel, times.join(" "));
+        } else {
+            println!("Tempo: {:.1} BPM", beats.bpm);
+            println!("Beats: {}", times.join(" "));
+        }
     }
 
-    // apply Hann window
-    let window = hann_window(n);
-    let mut input: Vec<Complex<f32>> = samples.iter()
-        .zip(window.iter())
-        .map(|(s,w)| Complex{ re: s*w, im: 0.0 })
-        .collect();
+    if let Some(segments) = &segments {
+        for (channel, segments) in segments.iter().enumerate() {
+            if separate {
+                println!("Channel {}:", channel);
+            }
+            let analysis = keys.analysis(channel, segments);
+            for (i, segment) in segments.iter().enumerate() {
+                let spelling = keys.spelling(channel, segment);
+                let line = format!(
+                    "{:8.3} {:8.3}  {:<8} {}",
+                    segment.start,
+                    segment.end,
+                    segment.chord.spelled_harte(spelling),
+                    segment.chord.spelled_name(spelling)
+                );
+                match analysis.as_ref().and_then(|analysis| analysis.numerals[i].as_ref()) {
+                    Some(numeral) => println!("{:<50} {}", line, numeral),
+                    None => println!("{}", line),
+                }
+            }
+            for cadence in analysis.iter().flat_map(|analysis| &analysis.cadences) {
+                println!("Cadence: {} at {:.3}", cadence.kind.name(), cadence.time);
+            }
+        }
+        return;
+    }
 
-    // FFT
-    let mut planner = FftPlanner::<f32>::new();
-    let fft = planner.plan_fft_forward(n);
-    fft.process(&mut input);
+    let results = results.unwrap_or_default();
+    let spellings: Vec<Spelling> = (0..results.len())
+        .map(|channel| keys.global(channel).map_or(Spelling::Sharps, Spelling::Key))
+        .collect();
 
-    // build pitch-class energy vector
-    let mut pitch_energy = [0.0f32; 12];
+    for (channel, result) in results.iter().enumerate() {
+        if separate {
+            println!("Channel {} pitch classes:", channel);
+        } else {
+            println!("Detected pitch classes:");
+        }
+        for pc in result.top_notes(3) {
+            println!("{}", spellings[channel].pitch_class(pc));
+        }
+    }
 
-    for (k, c) in input.iter().enumerate().take(n/2) {
-        let freq = k as f32 * sr as f32 / n as f32;
+    if !separate {
+        let (result, spelling) = (&results[0], spellings[0]);
+        println!("Detected chord: {} ({})", result.spelled_name(spelling), result.spelled_harte(spelling));
+        if result.root.is_some() {
+            println!("Chord tones: {}", result.tones(spelling).join(" "));
+        }
+        if !result.candidates.is_empty() {
+            println!("Candidates: {}", candidates(result, spelling));
+        }
+        return;
+    }
 
-        if freq < 70.0 || freq > 1500.0 {
-            continue;
+    for (channel, result) in results.iter().enumerate() {
+        let spelling = spellings[channel];
+        println!("Channel {}: {} ({})", channel, result.spelled_name(spelling), result.spelled_harte(spelling));
+        if result.root.is_some() {
+            println!("Channel {} chord tones: {}", channel, result.tones(spelling).join(" "));
+        }
+        if !result.candidates.is_empty() {
+            println!("Channel {} candidates: {}", channel, candidates(result, spelling));
         }
+    }
+    if results.windows(2).all(|pair| pair[0].harte() == pair[1].harte()) {
+        println!("All channels agree");
+    } else {
+        println!("Channels disagree");
+    }
+}
 
-        let mag = c.norm();
+// what is known about the key of every signal
+struct Keys {
+    // given with --in-key, overriding everything else
+    given: Option<Key>,
+    // of the whole recording
+    estimates: Option<Vec<KeyEstimate>>,
+    // over time
+    local: Option<Vec<Vec<KeySegment>>>,
+}
 
-        let pc = freq_to_pitch(freq);
-        pitch_energy[pc] += mag;
+impl Keys {
+    // the key of a whole signal
+    fn global(&self, channel: usize) -> Option<Key> {
+        self.given.or(self.estimates.as_ref().map(|estimates| estimates[channel].best.key))
     }
 
-    // normalize
-    if let Some(max_val) = pitch_energy.iter().cloned().reduce(f32::max) {
-        if max_val > 0.0 {
-            for v in pitch_energy.iter_mut() {
-                *v /= max_val;
-            }
+    // the key a chord segment is analyzed and spelled in, following modulations when tracked
+    fn at(&self, channel: usize, segment: &Segment) -> Option<Key> {
+        if self.given.is_some() {
+            return self.given;
+        }
+        match &self.local {
+            Some(local) => key::key_at(&local[channel], (segment.start + segment.end) / 2.0),
+            None => self.global(channel),
         }
     }
 
-    // get strongest 3 pitch classes
-    let mut indices: Vec<usize> = (0..12).collect();
-    indices.sort_by(|&a, &b| pitch_energy[a].partial_cmp(&pitch_energy[b]).unwrap());
-    let top_notes: Vec<usize> = indices.iter().rev().take(3).cloned().collect();
+    fn spelling(&self, channel: usize, segment: &Segment) -> Spelling {
+        self.at(channel, segment).map_or(Spelling::Sharps, Spelling::Key)
+    }
 
-    println!("Detected pitch classes:");
-    for &i in &top_notes {
-        println!("{}", NOTE_NAMES[i]);
+    // Roman numeral analysis of a signal's segments, when there is a key to analyze them in
+    fn analysis(&self, channel: usize, segments: &[Segment]) -> Option<Analysis> {
+        let keys: Vec<Key> = segments.iter().map(|segment| self.at(channel, segment)).collect::<Option<_>>()?;
+        Some(analyze_each(segments, &keys))
     }
+}
 
-    // chord matching
-    let mut best_score = 0.0;
-    let mut best_chord = "Unknown".to_string();
+// note attacks and beats of every signal, when asked for
+struct Timing {
+    onsets: Option<Vec<Vec<f32>>>,
+    beats: Option<Vec<Beats>>,
+}
 
-    for root in 0..12 {
-        let major = roll_template(&MAJOR_TEMPLATE, root);
-        let minor = roll_template(&MINOR_TEMPLATE, root);
+// a profile name, or the 12 major weights followed by the 12 minor ones
+fn parse_profile(value: &str) -> Option<Profile> {
+    if let Some(profile) = Profile::parse(value) {
+        return Some(profile);
+    }
 
-        let major_score = dot(&pitch_energy, &major);
-        let minor_score = dot(&pitch_energy, &minor);
+    let weights: Vec<f32> = value.split(',').map(|w| w.trim().parse().ok()).collect::<Option<_>>()?;
+    let (major, minor) = weights.split_at_checked(12)?;
+    Some(Profile::Custom { major: major.try_into().ok()?, minor: minor.try_into().ok()? })
+}
 
-        if major_score > best_score {
-            best_score = major_score;
-            best_chord = format!("{} major", NOTE_NAMES[root]);
-        }
+// e.g. "A minor (0.62), C major (0.21)"
+fn candidates(result: &ChordResult, spelling: Spelling) -> String {
+    let candidates: Vec<String> = result.candidates.iter()
+        .map(|candidate| format!("{} ({:.2})", candidate.spelled_name(spelling), candidate.probability))
+        .collect();
+    candidates.join(", ")
+}
 
-        if minor_score > best_score {
-            best_score = minor_score;
-            best_chord = format!("{} minor", NOTE_NAMES[root]);
-        }
+// the document printed by --format json, see the README for its schema. Every signal either has
+// segments or a single result
+fn json_report(
+    channel_mode: ChannelMode,
+    filename: &str,
+    references: &[f32],
+    keys: &Keys,
+    timing: &Timing,
+    segments: Option<&[Vec<Segment>]>,
+    results: Option<&[ChordResult]>,
+) -> Json {
+    let reports = references.iter().enumerate()
+        .map(|(i, &reference)| {
+            let channel = match channel_mode {
+                ChannelMode::Downmix => None,
+                ChannelMode::Channel(index) => Some(index),
+                ChannelMode::Separate => Some(i),
+            };
+            let tuning = Json::object([
+                ("cents", tuning::cents_from(reference).into()),
+                ("reference_hz", reference.into()),
+            ]);
+
+            // a given key takes the place of the estimate, which moves aside
+            let mut report = vec![("channel", channel.into()), ("tuning", tuning)];
+            match (keys.given, &keys.estimates) {
+                (Some(key), Some(estimates)) => {
+                    report.push(("key", key.into()));
+                    report.push(("key_estimate", (&estimates[i]).into()));
+                }
+                (Some(key), None) => report.push(("key", key.into())),
+                (None, Some(estimates)) => report.push(("key", (&estimates[i]).into())),
+                (None, None) => {}
+            }
+            if let Some(local) = &keys.local {
+                report.push(("key_segments", Json::Array(local[i].iter().map(Json::from).collect())));
+                report.push(("modulations", Json::Array(key::modulations(&local[i]).iter().map(Json::from).collect())));
+            }
+            if let Some(onsets) = &timing.onsets {
+                report.push(("onsets", onsets[i].clone().into()));
+            }
+            if let Some(beats) = &timing.beats {
+                report.push(("bpm", beats[i].bpm.into()));
+                report.push(("beats", beats[i].times.clone().into()));
+            }
+
+            match (segments, results) {
+                (Some(segments), _) => {
+                    let analysis = keys.analysis(i, &segments[i]);
+                    let segments = segments[i].iter().enumerate()
+                        .map(|(s, segment)| {
+                            let mut json = json::segment(segment, keys.spelling(i, segment));
+                            if let Some(analysis) = &analysis {
+                                json.push("numeral", analysis.numerals[s].as_ref().into());
+                            }
+                            json
+                        })
+                        .collect();
+                    report.push(("segments", Json::Array(segments)));
+                    if let Some(analysis) = &analysis {
+                        report.push(("cadences", Json::Array(analysis.cadences.iter().map(Json::from).collect())));
+                    }
+                }
+                (None, Some(results)) => {
+                    let spelling = keys.global(i).map_or(Spelling::Sharps, Spelling::Key);
+                    report.push(("chord", json::chord(&results[i], spelling)));
+                }
+                (None, None) => {}
+            }
+
+            Json::object(report)
+        })
+        .collect();
+
+    Json::object([
+        ("version", (json::SCHEMA_VERSION as usize).into()),
+        ("file", filename.into()),
+        ("signals", Json::Array(reports)),
+    ])
+}
+
+// scores the recognizer against reference annotations, either for one audio file or for every
+// WAV in a directory. Annotations are looked up by file stem, next to the audio unless
+// `references` points elsewhere
+fn run_evaluation(recognizer: &ChordRecognizer, audio: &str, references: Option<&String>) {
+    let audio = Path::new(audio);
+
+    let pairs: Vec<(PathBuf, PathBuf)> = if audio.is_dir() {
+        let lab_dir = references.map_or(audio, Path::new);
+        let entries = fs::read_dir(audio).unwrap_or_else(|e| fail(e.into()));
+        let mut wavs: Vec<PathBuf> = entries
+            .map(|entry| entry.map(|entry| entry.path()).unwrap_or_else(|e| fail(e.into())))
+            .filter(|path| path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("wav")))
+            .collect();
+        wavs.sort();
+
+        wavs.into_iter()
+            .filter_map(|wav| {
+                let lab = lab_dir.join(wav.file_stem()?).with_extension("lab");
+                if !lab.is_file() {
+                    eprintln!("Skipping {}: no annotation at {}", wav.display(), lab.display());
+                    return None;
+                }
+                Some((wav, lab))
+            })
+            .collect()
+    } else {
+        let lab = references.map_or_else(|| audio.with_extension("lab"), PathBuf::from);
+        vec![(audio.to_path_buf(), lab)]
+    };
+
+    let names: Vec<String> = pairs.iter()
+        .map(|(wav, _)| wav.file_name().unwrap_or_default().to_string_lossy().into_owned())
+        .collect();
+    let width = names.iter().map(String::len).chain([5]).max().unwrap_or(5);
+
+    print!("{:<width$} {:>8}", "File", "Duration");
+    for level in Level::ALL {
+        print!(" {:>8}", level.name());
     }
+    println!(" {:>8} {:>8} {:>8}", "overseg", "underseg", "seg");
 
-    if best_score < 0.4 {
-        return "Unknown".to_string();
+    let mut total = Evaluation::default();
+    for ((wav, lab), name) in pairs.iter().zip(&names) {
+        let reference = load_lab(lab).unwrap_or_else(|e| fail(e));
+        let segments = recognizer.segments_path(wav).unwrap_or_else(|e| fail(e)).remove(0);
+        let estimate: Vec<Annotation> = segments.iter().map(Annotation::from).collect();
+
+        let evaluation = evaluate(&reference, &estimate).unwrap_or_else(|e| fail(e));
+        print_scores(name, width, &evaluation);
+        total.merge(&evaluation);
+    }
+
+    if pairs.len() > 1 {
+        print_scores("Total", width, &total);
     }
 
-    best_chord
+    print_confusion(&total);
 }
 
-fn main() {
-    // TODO fix d major chord
-    let args: Vec<String> = env::args().collect();
+// one row of the evaluation table, scores in percent
+fn print_scores(name: &str, width: usize, evaluation: &Evaluation) {
+    let percent = |score: Option<f32>| score.map_or("-".to_string(), |s| format!("{:.1}", 100.0 * s));
 
-    if args.len() < 2 {
-        println!("Usage: cargo run -- <filename.wav>");
-        return;
+    print!("{:<width$} {:>7.1}s", name, evaluation.duration());
+    for level in Level::ALL {
+        print!(" {:>8}", percent(evaluation.wcsr(level)));
     }
+    println!(
+        " {:>8} {:>8} {:>8}",
+        percent(evaluation.over_segmentation()),
+        percent(evaluation.under_segmentation()),
+        percent(evaluation.segmentation())
+    );
+}
+
+// seconds of every reference chord (rows) labeled as every estimated chord (columns)
+fn print_confusion(evaluation: &Evaluation) {
+    let confusion = evaluation.confusion();
+    let mut columns: Vec<&String> = confusion.values().flat_map(|row| row.keys()).collect();
+    columns.sort();
+    columns.dedup();
+
+    let width = confusion.keys().chain(columns.iter().copied()).map(String::len).chain([6]).max().unwrap_or(6);
+
+    println!();
+    println!("Confusion matrix (seconds, rows: reference, columns: estimate)");
+    print!("{:<width$}", "");
+    for column in &columns {
+        print!(" {:>width$}", column);
+    }
+    println!();
+
+    for (reference, row) in confusion {
+        print!("{:<width$}", reference);
+        for column in &columns {
+            match row.get(*column) {
+                Some(seconds) => print!(" {:>width$.1}", seconds),
+                None => print!(" {:>width$}", "."),
+            }
+        }
+        println!();
+    }
+}
+
+// renders a chord or a progression to a WAV file, with a .lab annotation of it alongside
+fn run_synth(args: &[String]) {
+    let mut output: Option<String> = None;
+    let mut labels = vec!["C:maj".to_string()];
+    let mut progression: Option<String> = None;
+    let mut template = ChordSpec::default();
+    let mut synth = Synth::default();
+
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--chord" | "--progression" => {
+                let Some(value) = args.get(i + 1) else {
+                    usage(format!("{} expects a chord label, e.g. A:min/b3", args[i]));
+                };
+                if args[i] == "--chord" {
+                    labels = vec![value.clone()];
+                } else {
+                    progression = Some(value.clone());
+                }
+                i += 1;
+            }
+            "--timbre" => {
+                let Some(value) = args.get(i + 1).and_then(|v| Timbre::parse(v)) else {
+                    usage("--timbre expects one of sine, guitar, piano, organ");
+                };
+                template.timbre = value;
+                i += 1;
+            }
+            "--octave" => {
+                let Some(value) = args.get(i + 1).and_then(|v| v.parse::<i32>().ok()) else {
+                    usage("--octave expects the octave of the root, 4 being that of middle C");
+                };
+                template.octave = value;
+                i += 1;
+            }
+            "--detune" | "--noise" | "--duration" => {
+                let value = args.get(i + 1).and_then(|v| v.parse::<f32>().ok());
+                let Some(value) = value.filter(|&v| v >= 0.0) else {
+                    usage(format!("{} expects a number that isn't negative", args[i]));
+                };
+                match args[i].as_str() {
+                    "--detune" => template.detune_cents = value,
+                    "--noise" => template.noise = value,
+                    _ => template.duration = value,
+                }
+                i += 1;
+            }
+            "--sample-rate" => {
+                let value = args.get(i + 1).and_then(|v| v.parse::<usize>().ok());
+                let Some(value) = value.filter(|&v| v > 0) else {
+                    usage("--sample-rate expects a rate in Hz");
+                };
+                synth.sample_rate = value;
+                i += 1;
+            }
+            "--seed" => {
+                let Some(value) = args.get(i + 1).and_then(|v| v.parse::<u64>().ok()) else {
+                    usage("--seed expects a whole number");
+                };
+                synth.seed = value;
+                i += 1;
+            }
+            other if other.starts_with("--") => usage(format!("Unknown option {}", other)),
+            other => output = Some(other.to_string()),
+        }
+        i += 1;
+    }
+
+    let Some(output) = output else {
+        usage("Usage: cargo run -- synth <out.wav> [--chord LABEL | --progression \"LABEL,SECONDS ...\"] \
+                  [--octave N] [--timbre sine|guitar|piano|organ] [--detune CENTS] [--noise LEVEL] \
+                  [--duration SECONDS] [--sample-rate HZ] [--seed N]");
+    };
+
+    // a progression is a list of label,seconds pairs, e.g. "C:maj,2 A:min/b3,2 N,1"
+    let steps: Vec<(String, f32)> = match progression {
+        Some(progression) => {
+            let steps: Option<Vec<(String, f32)>> = progression.split_whitespace()
+                .map(|step| {
+                    let (label, seconds) = step.split_once(',')?;
+                    Some((label.to_string(), seconds.parse::<f32>().ok().filter(|&s| s > 0.0)?))
+                })
+                .collect();
+            let Some(steps) = steps else {
+                usage("--progression expects label,seconds pairs, e.g. \"C:maj,2 A:min/b3,2 N,1\"");
+            };
+            steps
+        }
+        None => labels.into_iter().map(|label| (label, template.duration)).collect(),
+    };
+
+    let steps: Vec<Step> = steps.into_iter()
+        .map(|(label, duration)| {
+            if label == "N" {
+                return Step::Rest(duration);
+            }
+            let chord = ChordSpec::from_harte(&label).unwrap_or_else(|e| fail(e));
+            Step::Chord(ChordSpec {
+                root: chord.root,
+                quality: chord.quality,
+                inversion: chord.inversion,
+                duration,
+                ..template.clone()
+            })
+        })
+        .collect();
+
+    let (samples, annotations) = synth.render_progression(&steps);
+    write_wav(&output, &samples, synth.sample_rate).unwrap_or_else(|e| fail(e));
 
-    let chord = find_chord(&args[1]);
-    println!("Detected chord: {}", chord);
+    let lab = Path::new(&output).with_extension("lab");
+    save_lab(&lab, &annotations).unwrap_or_else(|e| fail(e));
+    println!("Wrote {} and {}", output, lab.display());
 }
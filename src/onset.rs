@@ -0,0 +1,247 @@
+//! Onset detection: where notes start, from how the short-time spectrum changes between frames,
+//! after Bello et al., "A Tutorial on Onset Detection in Music Signals" (2005).
+
+use rustfft::num_complex::Complex;
+
+use crate::recognizer::Segment;
+use crate::stft::stft;
+
+/// What the onset detection function measures.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OnsetMethod {
+    /// Rise in magnitude, added up over every bin.
+    SpectralFlux,
+    /// Rise in energy weighted by frequency, picking out the broadband click of an attack.
+    Hfc,
+    /// Distance of every bin from where its magnitude and phase were heading, so a new note at an
+    /// unchanged level still shows up through its phase.
+    ComplexDomain,
+}
+
+impl OnsetMethod {
+    /// Parses `flux`, `hfc` or `complex`.
+    pub fn parse(name: &str) -> Option<OnsetMethod> {
+        match name {
+            "flux" => Some(OnsetMethod::SpectralFlux),
+            "hfc" => Some(OnsetMethod::Hfc),
+            "complex" => Some(OnsetMethod::ComplexDomain),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            OnsetMethod::SpectralFlux => "flux",
+            OnsetMethod::Hfc => "hfc",
+            OnsetMethod::ComplexDomain => "complex",
+        }
+    }
+}
+
+/// How onsets are detected and picked from the detection function.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OnsetDetection {
+    pub method: OnsetMethod,
+    /// Frame length in samples, much shorter than for chroma so attacks stay sharp.
+    pub frame_size: usize,
+    /// Distance between frames in samples.
+    pub hop: usize,
+    /// How far a peak has to rise above the moving median of the detection function, which is
+    /// scaled to peak at 1.
+    pub threshold: f32,
+    /// Seconds either side of a frame it has to be the largest within, and that the moving
+    /// median is taken over.
+    pub window: f32,
+    /// Shortest time between two onsets in seconds.
+    pub min_gap: f32,
+}
+
+impl Default for OnsetDetection {
+    fn default() -> OnsetDetection {
+        OnsetDetection {
+            method: OnsetMethod::SpectralFlux,
+            frame_size: 2048,
+            hop: 512,
+            threshold: 0.3,
+            window: 0.1,
+            min_gap: 0.05,
+        }
+    }
+}
+
+/// Onset detection function of `samples`, one value per hop from the first sample on, scaled so
+/// its largest value is 1. Frames are centred on their hop, so value `i` belongs to the time
+/// `i * hop / sample_rate`.
+pub fn detection_function(samples: &[f32], sample_rate: usize, detection: &OnsetDetection) -> Vec<f32> {
+    let OnsetDetection { frame_size, hop, .. } = *detection;
+    if frame_size == 0 || hop == 0 || sample_rate == 0 {
+        return Vec::new();
+    }
+
+    let bins = frame_size / 2;
+    let silence = vec![Complex::new(0.0f32, 0.0); bins];
+    let mut spectra = stft(samples, frame_size, hop);
+    for spectrum in spectra.iter_mut() {
+        spectrum.truncate(bins);
+    }
+
+    let mut odf: Vec<f32> = (0..spectra.len())
+        .map(|i| {
+            // the recording is taken to start from silence
+            let current = &spectra[i];
+            let previous = if i >= 1 { &spectra[i - 1] } else { &silence };
+            let before = if i >= 2 { &spectra[i - 2] } else { &silence };
+
+            match detection.method {
+                OnsetMethod::SpectralFlux => current.iter()
+                    .zip(previous)
+                    .map(|(c, p)| (c.norm() - p.norm()).max(0.0))
+                    .sum(),
+                OnsetMethod::Hfc => {
+                    let hfc = |spectrum: &[Complex<f32>]| -> f32 {
+                        spectrum.iter().enumerate().map(|(k, c)| k as f32 * c.norm_sqr()).sum()
+                    };
+                    (hfc(current) - hfc(previous)).max(0.0)
+                }
+                // only bins getting louder count, so notes dying away don't read as onsets
+                OnsetMethod::ComplexDomain => current.iter()
+                    .zip(previous)
+                    .zip(before)
+                    .filter(|((c, p), _)| c.norm() >= p.norm())
+                    .map(|((c, p), b)| {
+                        let phase = 2.0 * p.arg() - b.arg();
+                        (c - Complex::from_polar(p.norm(), phase)).norm()
+                    })
+                    .sum(),
+            }
+        })
+        .collect();
+
+    let max = odf.iter().cloned().fold(0.0, f32::max);
+    if max > 0.0 {
+        for value in odf.iter_mut() {
+            *value /= max;
+        }
+    }
+
+    odf
+}
+
+/// Indices of the peaks of a detection function sampled `frame_rate` times a second: local
+/// maxima standing `threshold` above the moving median, at least `min_gap` apart.
+pub fn pick_peaks(odf: &[f32], frame_rate: f32, detection: &OnsetDetection) -> Vec<usize> {
+    let half = (detection.window.max(0.0) * frame_rate).round() as usize;
+    let gap = (detection.min_gap.max(0.0) * frame_rate).round() as usize;
+
+    let mut peaks: Vec<usize> = Vec::new();
+    let mut sorted = Vec::with_capacity(2 * half + 1);
+
+    for (i, &value) in odf.iter().enumerate() {
+        if value <= 0.0 {
+            continue;
+        }
+
+        let around = &odf[i.saturating_sub(half)..(i + half + 1).min(odf.len())];
+        if around.iter().any(|&v| v > value) {
+            continue;
+        }
+
+        sorted.clear();
+        sorted.extend_from_slice(around);
+        sorted.sort_by(f32::total_cmp);
+        if value < sorted[sorted.len() / 2] + detection.threshold {
+            continue;
+        }
+
+        // of two peaks too close together, or a flat top, the first one stands
+        if peaks.last().is_some_and(|&last| i - last < gap.max(1)) {
+            continue;
+        }
+        peaks.push(i);
+    }
+
+    peaks
+}
+
+/// Times of the onsets in `samples`, in seconds.
+pub fn detect_onsets(samples: &[f32], sample_rate: usize, detection: &OnsetDetection) -> Vec<f32> {
+    let odf = detection_function(samples, sample_rate, detection);
+    let frame_rate = sample_rate as f32 / detection.hop.max(1) as f32;
+
+    pick_peaks(&odf, frame_rate, detection).into_iter()
+        .map(|i| i as f32 / frame_rate)
+        .collect()
+}
+
+/// Moves every change between consecutive segments to the nearest onset at most `tolerance`
+/// seconds away. A change stays on the frame grid when no onset is close enough, or when moving
+/// it would leave either segment empty.
+pub fn snap_to_onsets(segments: &mut [Segment], onsets: &[f32], tolerance: f32) {
+    for i in 1..segments.len() {
+        let change = segments[i].start;
+        let (earliest, latest) = (segments[i - 1].start, segments[i].end);
+
+        let nearest = onsets.iter()
+            .cloned()
+            .filter(|&onset| earliest < onset && onset < latest)
+            .min_by(|a, b| (a - change).abs().total_cmp(&(b - change).abs()));
+
+        if let Some(onset) = nearest.filter(|onset| (onset - change).abs() <= tolerance) {
+            segments[i - 1].end = onset;
+            segments[i].start = onset;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recognizer::{ChordRecognizer, Config};
+    use crate::synth::render_chords;
+
+    fn progression() -> (Vec<f32>, Vec<f32>) {
+        let chords = [("C:maj", 0.8), ("A:min", 0.8), ("F:maj", 0.8), ("G:maj", 0.8), ("C:maj", 0.8)];
+        let (samples, labels) = render_chords(&chords);
+        (samples, labels.iter().map(|label| label.start).collect())
+    }
+
+    #[test]
+    fn finds_chord_attacks() {
+        let (samples, starts) = progression();
+
+        for method in [OnsetMethod::SpectralFlux, OnsetMethod::Hfc, OnsetMethod::ComplexDomain] {
+            let detection = OnsetDetection { method, ..OnsetDetection::default() };
+            let onsets = detect_onsets(&samples, 44100, &detection);
+
+            assert_eq!(onsets.len(), starts.len(), "{}: {:?}", method.name(), onsets);
+            for (onset, start) in onsets.iter().zip(&starts) {
+                assert!((onset - start).abs() < 0.03, "{}: {:?}", method.name(), onsets);
+            }
+        }
+    }
+
+    // chord changes land on the frame grid, and a guitar's attack taking over frames centred just
+    // before it pulls them early. Snapping puts them on the attacks
+    #[test]
+    fn snaps_chord_changes() {
+        let (samples, starts) = progression();
+        let off_by = |onset_snap| {
+            let recognizer = ChordRecognizer::new(Config { onset_snap, ..Config::default() });
+            let segments = recognizer.segments(&samples, 44100).unwrap();
+            segments.iter()
+                .zip(&starts)
+                .map(|(segment, start)| (segment.start - start).abs())
+                .fold(0.0, f32::max)
+        };
+
+        assert!(off_by(None) > 0.03);
+        assert!(off_by(Some(0.2)) < 0.01);
+    }
+
+    #[test]
+    fn peaks_stand_above_the_median() {
+        let detection = OnsetDetection { window: 2.0, min_gap: 2.0, ..OnsetDetection::default() };
+        let odf = [0.0, 0.2, 1.0, 0.3, 0.25, 0.3, 0.2, 0.1, 0.5, 0.95, 0.2];
+        assert_eq!(pick_peaks(&odf, 1.0, &detection), [2, 9]);
+    }
+}
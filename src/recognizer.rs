@@ -0,0 +1,897 @@
+use std::borrow::Cow;
+use std::io::Read;
+use std::ops::Range;
+use std::path::Path;
+use std::slice;
+
+use crate::audio::{self, ChannelMode};
+use crate::beat::{self, BeatTracking, Beats, Grid};
+use crate::chords::{ChordSet, NOTE_NAMES, best_state, chord_name, harte_label};
+use crate::chroma::{BASS_NOTES, Chroma, ChromaExtractor, FrontEnd, normalize};
+use crate::error::ChordError;
+use crate::hmm::Hmm;
+use crate::hpss::{self, Hpss};
+use crate::key::{self, Key, KeyEstimate, KeySegment, Profile};
+use crate::onset::{self, OnsetDetection};
+use crate::similarity::{Metric, Similarity};
+use crate::spelling::Spelling;
+use crate::tuning;
+use crate::vocabulary::{Quality, Vocabulary};
+
+// inputs whose loudest sample stays below this (about -100 dBFS) are treated as silent
+const SILENCE_LEVEL: f32 = 1e-5;
+
+// how sharply a template score difference turns into a likelihood difference when smoothing
+const EMISSION_SHARPNESS: f32 = 10.0;
+
+// the same for key profile correlations when tracking the key
+const KEY_SHARPNESS: f32 = 10.0;
+
+/// Viterbi smoothing of frame-level chords in segmented mode.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Smoothing {
+    /// Probability of a chord continuing into the next frame.
+    pub self_prob: f32,
+    /// Favour changes between chords close on the circle of fifths.
+    pub fifths: bool,
+}
+
+/// Frames too quiet or too noise-like to hold a chord are labeled as no chord (N) outright.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Gate {
+    /// Frames whose RMS level, once windowed, stays below this many dBFS are silent.
+    pub min_rms_db: f32,
+    /// Frames whose spectral flatness exceeds this are noise or percussion. Flatness runs from
+    /// near 0 for a few clear pitches up to about 0.56 for white noise.
+    pub max_flatness: f32,
+}
+
+impl Default for Gate {
+    fn default() -> Gate {
+        Gate { min_rms_db: -60.0, max_flatness: 0.3 }
+    }
+}
+
+/// Tracking of the key over time, from pitch-class energy summed over a sliding window and
+/// smoothed with an HMM over the 24 keys.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeyTracking {
+    /// Length of the window in seconds, centred on each frame.
+    pub window: f32,
+    /// Probability of the key continuing into the next frame.
+    pub self_prob: f32,
+}
+
+impl Default for KeyTracking {
+    fn default() -> KeyTracking {
+        KeyTracking { window: 8.0, self_prob: 0.995 }
+    }
+}
+
+/// Everything that can be tuned about recognition.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    pub vocabulary: Vocabulary,
+    /// How chroma is scored against chord templates.
+    pub metric: Metric,
+    pub front_end: FrontEnd,
+    /// Used when reading files or readers, slices are always a single signal.
+    pub channel_mode: ChannelMode,
+    /// Frequency of A4, estimated from the audio when None. Has to lie within
+    /// `tuning::REFERENCE_RANGE`.
+    pub reference_hz: Option<f32>,
+    /// Attribute overtones back to their fundamentals before matching.
+    pub harmonics: bool,
+    /// Separate the harmonic part of the signal from drums and other percussion and compute
+    /// chroma from it alone, None to use the signal as it is.
+    pub hpss: Option<Hpss>,
+    /// Frame length in samples for segmented recognition.
+    pub frame_size: usize,
+    /// Distance between frames in samples for segmented recognition.
+    pub hop: usize,
+    pub smoothing: Option<Smoothing>,
+    /// Silence and noise gating, None to always pick a chord by its template score.
+    pub gate: Option<Gate>,
+    /// Softmax temperature turning template scores into probabilities, lower is more decisive.
+    pub temperature: f32,
+    /// How many of the most likely chords every result lists as candidates.
+    pub candidates: usize,
+    /// Key profiles the pitch-class energy is correlated with to estimate the key.
+    pub key_profile: Profile,
+    pub key_tracking: KeyTracking,
+    pub onsets: OnsetDetection,
+    /// Move chord changes in segmented recognition to the nearest onset at most this many
+    /// seconds away, None to leave them on the frame grid.
+    pub onset_snap: Option<f32>,
+    pub beats: BeatTracking,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            vocabulary: Vocabulary::MajMin,
+            metric: Metric::Dot,
+            front_end: FrontEnd::Fft,
+            channel_mode: ChannelMode::Downmix,
+            reference_hz: None,
+            harmonics: true,
+            hpss: None,
+            frame_size: 8192,
+            hop: 2048,
+            smoothing: None,
+            gate: Some(Gate::default()),
+            temperature: 0.1,
+            candidates: 3,
+            key_profile: Profile::KrumhanslKessler,
+            key_tracking: KeyTracking::default(),
+            onsets: OnsetDetection::default(),
+            onset_snap: None,
+            beats: BeatTracking::default(),
+        }
+    }
+}
+
+/// A recognized chord.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChordResult {
+    /// Pitch class of the root, 0 being C. None when no chord was recognized.
+    pub root: Option<usize>,
+    pub quality: Option<&'static Quality>,
+    /// Pitch class of the bass note, only set when it differs from the root.
+    pub bass: Option<usize>,
+    /// Template score of the chord.
+    pub score: f32,
+    /// Probability of the chord among every chord of the vocabulary and no chord.
+    pub probability: f32,
+    /// The most likely chords, most likely first, leaving out those with no chance at all, such as
+    /// the ones the gate ruled out.
+    pub candidates: Vec<Candidate>,
+    /// Max-normalized pitch-class energy the chord was matched against.
+    pub chroma: [f32; 12],
+}
+
+impl ChordResult {
+    /// Human readable name, e.g. "C major/E", or "No chord".
+    pub fn name(&self) -> String {
+        self.spelled_name(Spelling::Sharps)
+    }
+
+    /// Harte label, e.g. "C:maj/3", or "N" for no chord.
+    pub fn harte(&self) -> String {
+        self.spelled_harte(Spelling::Sharps)
+    }
+
+    /// Like [`ChordResult::name`], with the root and bass note spelled by `spelling`.
+    pub fn spelled_name(&self, spelling: Spelling) -> String {
+        chord_name(self.chord(), self.bass, spelling)
+    }
+
+    /// Like [`ChordResult::harte`], with the root spelled by `spelling`.
+    pub fn spelled_harte(&self, spelling: Spelling) -> String {
+        harte_label(self.chord(), self.bass, spelling)
+    }
+
+    /// Names of the chord tones, root first, empty for no chord.
+    pub fn tones(&self, spelling: Spelling) -> Vec<String> {
+        self.chord().map_or(Vec::new(), |(root, quality)| spelling.chord_tones(root, quality))
+    }
+
+    /// The `n` strongest pitch classes, strongest first.
+    pub fn top_notes(&self, n: usize) -> Vec<usize> {
+        let mut indices: Vec<usize> = (0..12).collect();
+        indices.sort_by(|&a, &b| self.chroma[b].total_cmp(&self.chroma[a]));
+        indices.truncate(n);
+        indices
+    }
+
+    /// Names of the `n` strongest pitch classes, strongest first.
+    pub fn top_note_names(&self, n: usize) -> Vec<&'static str> {
+        self.top_notes(n).into_iter().map(|pc| NOTE_NAMES[pc]).collect()
+    }
+
+    fn chord(&self) -> Option<(usize, &'static Quality)> {
+        Some((self.root?, self.quality?))
+    }
+}
+
+/// A chord the input could have been, without its bass note.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Candidate {
+    /// Pitch class of the root, None for no chord.
+    pub root: Option<usize>,
+    pub quality: Option<&'static Quality>,
+    /// Template score. Chords the gate ruled out have no chance and aren't candidates, except in
+    /// a segment where it only ruled them out for some frames, which leaves the score at negative
+    /// infinity.
+    pub score: f32,
+    /// Softmax of the template scores, summing to 1 over all chords.
+    pub probability: f32,
+}
+
+impl Candidate {
+    /// Human readable name, e.g. "A minor", or "No chord".
+    pub fn name(&self) -> String {
+        self.spelled_name(Spelling::Sharps)
+    }
+
+    /// Harte label, e.g. "A:min", or "N" for no chord.
+    pub fn harte(&self) -> String {
+        self.spelled_harte(Spelling::Sharps)
+    }
+
+    /// Like [`Candidate::name`], with the root spelled by `spelling`.
+    pub fn spelled_name(&self, spelling: Spelling) -> String {
+        chord_name(self.chord(), None, spelling)
+    }
+
+    /// Like [`Candidate::harte`], with the root spelled by `spelling`.
+    pub fn spelled_harte(&self, spelling: Spelling) -> String {
+        harte_label(self.chord(), None, spelling)
+    }
+
+    fn chord(&self) -> Option<(usize, &'static Quality)> {
+        Some((self.root?, self.quality?))
+    }
+}
+
+/// A stretch of the recording over which the same chord was detected, times in seconds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Segment {
+    pub start: f32,
+    pub end: f32,
+    pub chord: ChordResult,
+}
+
+/// Recognizes chords in mono audio according to a [`Config`].
+pub struct ChordRecognizer {
+    config: Config,
+    chords: ChordSet,
+    hmm: Option<Hmm>,
+}
+
+impl ChordRecognizer {
+    pub fn new(config: Config) -> ChordRecognizer {
+        let similarity = config.metric.similarity();
+        ChordRecognizer::with_similarity(config, similarity)
+    }
+
+    /// Like [`ChordRecognizer::new`], scoring templates with a custom similarity measure
+    /// instead of the configured metric.
+    pub fn with_similarity(config: Config, similarity: Box<dyn Similarity>) -> ChordRecognizer {
+        let chords = ChordSet::new(config.vocabulary, similarity);
+        let hmm = config.smoothing.map(|smoothing| {
+            if smoothing.fifths {
+                Hmm::from_weights(&chords.fifths_weights(), smoothing.self_prob)
+            } else {
+                Hmm::uniform(chords.n_states(), smoothing.self_prob)
+            }
+        });
+
+        ChordRecognizer { config, chords, hmm }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Frequency of A4 used for `samples`, either configured or estimated from them.
+    pub fn reference_hz(&self, samples: &[f32], sample_rate: usize) -> Result<f32, ChordError> {
+        check_samples(samples)?;
+
+        match self.config.reference_hz {
+            Some(hz) if !tuning::REFERENCE_RANGE.contains(&hz) => Err(ChordError::InvalidReference(hz)),
+            Some(hz) => Ok(hz),
+            None => Ok(tuning::reference_hz(tuning::estimate_tuning(samples, sample_rate))),
+        }
+    }
+
+    /// One chord for the whole of `samples`.
+    pub fn recognize(&self, samples: &[f32], sample_rate: usize) -> Result<ChordResult, ChordError> {
+        let reference = self.reference_hz(samples, sample_rate)?;
+
+        // constant-Q kernels are no longer than the frame they are built for and sit in its
+        // middle, so over the whole signal they would only hear its middle. They are run over
+        // frames instead and the chroma summed
+        let Config { frame_size, hop, .. } = self.config;
+        let chroma = match self.config.front_end {
+            FrontEnd::ConstantQ { .. } if frame_size > 0 && hop > 0 => {
+                Chroma::mean(&self.frames(samples, sample_rate, reference))
+            }
+            _ => {
+                let extractor = ChromaExtractor::new(
+                    sample_rate,
+                    samples.len(),
+                    self.config.front_end,
+                    reference,
+                    self.config.harmonics,
+                );
+                extractor.chroma(&self.chroma_input(samples))
+            }
+        };
+
+        let scores = self.scores(&chroma);
+        let probabilities = self.probabilities(&scores);
+        let state = best_state(&scores);
+
+        Ok(self.result(state, &scores, &probabilities, chroma.treble, &chroma.bass))
+    }
+
+    /// Runs the chord matcher on overlapping frames and merges neighbouring frames with the same
+    /// chord into segments. With smoothing configured the chord path is Viterbi decoded instead
+    /// of picked frame by frame, and with onset snapping configured the changes are moved onto
+    /// the nearest note attacks.
+    pub fn segments(&self, samples: &[f32], sample_rate: usize) -> Result<Vec<Segment>, ChordError> {
+        let reference = self.reference_hz(samples, sample_rate)?;
+
+        let Config { frame_size, hop, .. } = self.config;
+        if frame_size == 0 || hop == 0 || sample_rate == 0 {
+            return Ok(Vec::new());
+        }
+
+        let frames = self.frames(samples, sample_rate, reference);
+        let frame_scores: Vec<Vec<f32>> = frames.iter().map(|chroma| self.scores(chroma)).collect();
+        let states = self.decode(&frame_scores);
+
+        // frames belonging to each segment, as (first sample, end sample, state, first frame,
+        // frame count)
+        let mut runs: Vec<(usize, usize, usize, usize, usize)> = Vec::new();
+
+        // each frame is credited with the hop around its centre, the first one from the start
+        // and the last one up to the end
+        let edge = |i: usize| match i {
+            0 => 0,
+            i if i == states.len() => samples.len(),
+            i => (i * hop + frame_size / 2).saturating_sub(hop / 2).min(samples.len()),
+        };
+
+        for (i, &state) in states.iter().enumerate() {
+            let (start, stop) = (edge(i), edge(i + 1));
+
+            match runs.last_mut() {
+                Some(last) if last.2 == state => {
+                    last.1 = stop;
+                    last.4 += 1;
+                }
+                _ => runs.push((start, stop, state, i, 1)),
+            }
+        }
+
+        let frame_probabilities: Vec<Vec<f32>> = frame_scores.iter()
+            .map(|scores| self.probabilities(scores))
+            .collect();
+
+        // chroma and bass energy are summed over each segment, so the bass note is decided once
+        // per segment, while scores and probabilities are averaged
+        let mut segments: Vec<Segment> = runs.into_iter()
+            .map(|(start, end, state, first, count)| {
+                let mut treble = [0.0f32; 12];
+                let bass = self.bass_within(&frames[first..first + count], first, start..end, samples.len());
+                let mut scores = vec![0.0f32; self.chords.n_states()];
+                let mut probabilities = vec![0.0f32; self.chords.n_states()];
+
+                for i in first..first + count {
+                    for (t, f) in treble.iter_mut().zip(frames[i].treble.iter()) {
+                        *t += f;
+                    }
+                    for (s, f) in scores.iter_mut().zip(frame_scores[i].iter()) {
+                        *s += f / count as f32;
+                    }
+                    for (p, f) in probabilities.iter_mut().zip(frame_probabilities[i].iter()) {
+                        *p += f / count as f32;
+                    }
+                }
+
+                normalize(&mut treble);
+                let chord = self.result(state, &scores, &probabilities, treble, &bass);
+                Segment { start: start as f32 / sample_rate as f32, end: end as f32 / sample_rate as f32, chord }
+            })
+            .collect();
+
+        if let Some(tolerance) = self.config.onset_snap {
+            let onsets = onset::detect_onsets(samples, sample_rate, &self.config.onsets);
+            onset::snap_to_onsets(&mut segments, &onsets, tolerance);
+        }
+
+        Ok(segments)
+    }
+
+    /// The tempo of `samples` and where their beats fall.
+    pub fn beats(&self, samples: &[f32], sample_rate: usize) -> Result<Beats, ChordError> {
+        check_samples(samples)?;
+        Ok(beat::track_beats(samples, sample_rate, &self.config.onsets, &self.config.beats))
+    }
+
+    /// One chord per beat or bar of `beats`, matched against the chroma of the frames centred
+    /// within it. Anything before the first beat gets a segment of its own. With smoothing
+    /// configured the chords are Viterbi decoded from beat to beat.
+    pub fn beat_segments(
+        &self,
+        samples: &[f32],
+        sample_rate: usize,
+        beats: &Beats,
+        grid: Grid,
+    ) -> Result<Vec<Segment>, ChordError> {
+        let reference = self.reference_hz(samples, sample_rate)?;
+
+        let Config { frame_size, hop, .. } = self.config;
+        if frame_size == 0 || hop == 0 || sample_rate == 0 {
+            return Ok(Vec::new());
+        }
+
+        let frames = self.frames(samples, sample_rate, reference);
+        let duration = samples.len() as f32 / sample_rate as f32;
+        let boundaries = beats.grid(grid, self.config.beats.beats_per_bar, duration);
+
+        // frame i is centred on sample i * hop + frame_size / 2. A beat shorter than the hop
+        // that no frame is centred in takes the frame nearest its middle
+        let frame_at = |time: f32| (time * sample_rate as f32 - (frame_size / 2) as f32) / hop as f32;
+        let chroma: Vec<Chroma> = boundaries.windows(2)
+            .map(|pair| {
+                let first = (frame_at(pair[0]).ceil().max(0.0) as usize).min(frames.len() - 1);
+                let last = (frame_at(pair[1]).ceil().max(0.0) as usize).min(frames.len());
+                if first < last {
+                    let span = (pair[0] * sample_rate as f32) as usize..(pair[1] * sample_rate as f32) as usize;
+                    let bass = self.bass_within(&frames[first..last], first, span, samples.len());
+                    Chroma { bass, ..Chroma::mean(&frames[first..last]) }
+                } else {
+                    let nearest = frame_at((pair[0] + pair[1]) / 2.0).round().max(0.0) as usize;
+                    Chroma::mean(slice::from_ref(&frames[nearest.min(frames.len() - 1)]))
+                }
+            })
+            .collect();
+
+        let beat_scores: Vec<Vec<f32>> = chroma.iter().map(|chroma| self.scores(chroma)).collect();
+        let states = self.decode(&beat_scores);
+
+        let segments = boundaries.windows(2)
+            .zip(chroma)
+            .zip(states.into_iter().zip(&beat_scores))
+            .map(|((pair, chroma), (state, scores))| {
+                let probabilities = self.probabilities(scores);
+                let chord = self.result(state, scores, &probabilities, chroma.treble, &chroma.bass);
+                Segment { start: pair[0], end: pair[1], chord }
+            })
+            .collect();
+
+        Ok(segments)
+    }
+
+    /// Times of the note attacks in `samples`, in seconds.
+    pub fn onsets(&self, samples: &[f32], sample_rate: usize) -> Result<Vec<f32>, ChordError> {
+        check_samples(samples)?;
+        Ok(onset::detect_onsets(samples, sample_rate, &self.config.onsets))
+    }
+
+    /// The key of `samples`, from their pitch-class energy summed over every frame the gate lets
+    /// through.
+    pub fn key(&self, samples: &[f32], sample_rate: usize) -> Result<KeyEstimate, ChordError> {
+        let reference = self.reference_hz(samples, sample_rate)?;
+
+        let mut chroma = [0.0f32; 12];
+        if self.config.frame_size > 0 && self.config.hop > 0 && sample_rate > 0 {
+            for frame in self.frames(samples, sample_rate, reference) {
+                if self.gated(&frame) {
+                    continue;
+                }
+                for (c, f) in chroma.iter_mut().zip(frame.treble.iter()) {
+                    *c += f;
+                }
+            }
+        }
+        normalize(&mut chroma);
+
+        Ok(key::estimate_key(&chroma, &self.config.key_profile))
+    }
+
+    /// The key of `samples` over time, following modulations. Silent and noisy frames are left
+    /// out of the window sums like they are for the global key.
+    pub fn key_segments(&self, samples: &[f32], sample_rate: usize) -> Result<Vec<KeySegment>, ChordError> {
+        let reference = self.reference_hz(samples, sample_rate)?;
+
+        let Config { hop, key_tracking, .. } = self.config;
+        if self.config.frame_size == 0 || hop == 0 || sample_rate == 0 {
+            return Ok(Vec::new());
+        }
+
+        let frames: Vec<[f32; 12]> = self.frames(samples, sample_rate, reference).iter()
+            .map(|frame| if self.gated(frame) { [0.0; 12] } else { frame.treble })
+            .collect();
+
+        // running sums, so every window costs the same however long it is
+        let mut sums = vec![[0.0f32; 12]; frames.len() + 1];
+        for (i, frame) in frames.iter().enumerate() {
+            for pc in 0..12 {
+                sums[i + 1][pc] = sums[i][pc] + frame[pc];
+            }
+        }
+
+        // windows near either end slide inwards rather than shrink, so the first and last frames
+        // don't hang on the last few chords
+        let half = (key_tracking.window.max(0.0) * sample_rate as f32 / hop as f32 / 2.0).round() as usize;
+        let width = (2 * half + 1).min(frames.len());
+        let frame_scores: Vec<Vec<f32>> = (0..frames.len())
+            .map(|i| {
+                let first = i.saturating_sub(half).min(frames.len() - width);
+                let last = first + width;
+                let mut chroma = [0.0f32; 12];
+                for pc in 0..12 {
+                    chroma[pc] = sums[last][pc] - sums[first][pc];
+                }
+                normalize(&mut chroma);
+                key::key_scores(&chroma, &self.config.key_profile).iter().map(|s| s.score).collect()
+            })
+            .collect();
+
+        let log_emissions: Vec<Vec<f32>> = frame_scores.iter()
+            .map(|scores| scores.iter().map(|s| KEY_SHARPNESS * s).collect())
+            .collect();
+        let hmm = Hmm::from_weights(&key::transition_weights(), key_tracking.self_prob);
+        let keys: Vec<Key> = Key::all().collect();
+
+        let mut segments: Vec<KeySegment> = Vec::new();
+        let mut count = 0;
+        for (i, state) in hmm.viterbi(&log_emissions).into_iter().enumerate() {
+            let start = (i * hop) as f32 / sample_rate as f32;
+            let end = ((i + 1) * hop).min(samples.len()) as f32 / sample_rate as f32;
+            let score = frame_scores[i][state];
+
+            match segments.last_mut() {
+                Some(last) if last.key == keys[state] => {
+                    last.end = end;
+                    last.score += (score - last.score) / (count + 1) as f32;
+                    count += 1;
+                }
+                _ => {
+                    segments.push(KeySegment { start, end, key: keys[state], score });
+                    count = 1;
+                }
+            }
+        }
+
+        Ok(segments)
+    }
+
+    /// Recognizes one chord per signal selected from a WAV file by the configured channel mode.
+    pub fn recognize_path(&self, path: impl AsRef<Path>) -> Result<Vec<ChordResult>, ChordError> {
+        let (signals, sr) = audio::open_wav(path, self.config.channel_mode)?;
+        signals.iter().map(|samples| self.recognize(samples, sr)).collect()
+    }
+
+    /// Like [`ChordRecognizer::recognize_path`], reading WAV data from `reader`.
+    pub fn recognize_reader<R: Read>(&self, reader: R) -> Result<Vec<ChordResult>, ChordError> {
+        let (signals, sr) = audio::read_wav(reader, self.config.channel_mode)?;
+        signals.iter().map(|samples| self.recognize(samples, sr)).collect()
+    }
+
+    /// Segments every signal selected from a WAV file by the configured channel mode.
+    pub fn segments_path(&self, path: impl AsRef<Path>) -> Result<Vec<Vec<Segment>>, ChordError> {
+        let (signals, sr) = audio::open_wav(path, self.config.channel_mode)?;
+        signals.iter().map(|samples| self.segments(samples, sr)).collect()
+    }
+
+    /// Like [`ChordRecognizer::segments_path`], reading WAV data from `reader`.
+    pub fn segments_reader<R: Read>(&self, reader: R) -> Result<Vec<Vec<Segment>>, ChordError> {
+        let (signals, sr) = audio::read_wav(reader, self.config.channel_mode)?;
+        signals.iter().map(|samples| self.segments(samples, sr)).collect()
+    }
+
+    // chroma of overlapping frames of `samples`, frame i centred on sample i * hop + frame_size / 2.
+    // Frame size and hop have to be positive
+    fn frames(&self, samples: &[f32], sample_rate: usize, reference: f32) -> Vec<Chroma> {
+        let Config { frame_size, hop, .. } = self.config;
+        let extractor = ChromaExtractor::new(
+            sample_rate,
+            frame_size,
+            self.config.front_end,
+            reference,
+            self.config.harmonics,
+        );
+
+        let samples = self.chroma_input(samples);
+
+        // zero pad the tail so the last samples still get a frame. Frames centred past the end
+        // would be mostly padding, where the window has all but faded out, and are left out
+        // unless the signal is shorter than half a frame
+        let mut frame = vec![0.0f32; frame_size];
+        let mut frames = Vec::new();
+
+        let centred = |&pos: &usize| pos == 0 || pos + frame_size / 2 < samples.len();
+        for pos in (0..samples.len()).step_by(hop).take_while(centred) {
+            let end = (pos + frame_size).min(samples.len());
+            frame.fill(0.0);
+            frame[..end - pos].copy_from_slice(&samples[pos..end]);
+            frames.push(extractor.chroma(&frame));
+        }
+
+        frames
+    }
+
+    // bass energy summed over those of `frames`, numbered from `first`, that lie wholly within
+    // the samples of `span`, so a bass note held over from the chord before or already sounding
+    // for the one after doesn't make an inversion. A span running to the end of the `len`
+    // samples takes in the padded frames there, and one too short for any frame to fit in takes
+    // every frame
+    fn bass_within(&self, frames: &[Chroma], first: usize, span: Range<usize>, len: usize) -> [f32; BASS_NOTES] {
+        let Config { frame_size, hop, .. } = self.config;
+        let within = |i: usize| {
+            let pos = (first + i) * hop;
+            span.start <= pos && (span.end >= len || pos + frame_size <= span.end)
+        };
+
+        let mut bass = [0.0f32; BASS_NOTES];
+        let inside = (0..frames.len()).any(within);
+        for (_, frame) in frames.iter().enumerate().filter(|&(i, _)| !inside || within(i)) {
+            for (b, f) in bass.iter_mut().zip(frame.bass.iter()) {
+                *b += f;
+            }
+        }
+
+        bass
+    }
+
+    // what chroma is computed from, the harmonic part alone when separation is configured
+    fn chroma_input<'a>(&self, samples: &'a [f32]) -> Cow<'a, [f32]> {
+        match &self.config.hpss {
+            Some(hpss) => Cow::Owned(hpss::separate(samples, hpss).harmonic),
+            None => Cow::Borrowed(samples),
+        }
+    }
+
+    // the chord of every frame, Viterbi decoded when smoothing is configured
+    fn decode(&self, frame_scores: &[Vec<f32>]) -> Vec<usize> {
+        match &self.hmm {
+            Some(hmm) => {
+                let log_emissions: Vec<Vec<f32>> = frame_scores.iter()
+                    .map(|scores| scores.iter().map(|s| EMISSION_SHARPNESS * s).collect())
+                    .collect();
+                hmm.viterbi(&log_emissions)
+            }
+            None => frame_scores.iter().map(|scores| best_state(scores)).collect(),
+        }
+    }
+
+    // whether the gate closes on a frame as silence or noise
+    fn gated(&self, chroma: &Chroma) -> bool {
+        self.config.gate.is_some_and(|gate| {
+            chroma.rms_db < gate.min_rms_db || chroma.flatness > gate.max_flatness
+        })
+    }
+
+    // template scores of every chord, or only no chord when the gate closes on the frame
+    fn scores(&self, chroma: &Chroma) -> Vec<f32> {
+        if self.gated(chroma) {
+            self.chords.gated_scores()
+        } else {
+            self.chords.scores(&chroma.treble)
+        }
+    }
+
+    // softmax over the scores of every state
+    fn probabilities(&self, scores: &[f32]) -> Vec<f32> {
+        let temperature = self.config.temperature.max(f32::MIN_POSITIVE);
+        let max = scores.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+
+        let mut probabilities: Vec<f32> = scores.iter().map(|s| ((s - max) / temperature).exp()).collect();
+        let sum: f32 = probabilities.iter().sum();
+        for p in probabilities.iter_mut() {
+            *p /= sum;
+        }
+
+        probabilities
+    }
+
+    fn result(
+        &self,
+        state: usize,
+        scores: &[f32],
+        probabilities: &[f32],
+        chroma: [f32; 12],
+        bass: &[f32; BASS_NOTES],
+    ) -> ChordResult {
+        let chord = self.chords.chord(state);
+
+        let mut ranked: Vec<usize> = (0..scores.len()).filter(|&s| probabilities[s] > 0.0).collect();
+        ranked.sort_by(|&a, &b| probabilities[b].total_cmp(&probabilities[a]));
+        let candidates = ranked.into_iter()
+            .take(self.config.candidates)
+            .map(|s| {
+                let chord = self.chords.chord(s);
+                Candidate {
+                    root: chord.map(|(root, _)| root),
+                    quality: chord.map(|(_, quality)| quality),
+                    score: scores[s],
+                    probability: probabilities[s],
+                }
+            })
+            .collect();
+
+        ChordResult {
+            root: chord.map(|(root, _)| root),
+            quality: chord.map(|(_, quality)| quality),
+            bass: self.chords.bass(state, bass),
+            score: scores[state],
+            probability: probabilities[state],
+            candidates,
+            chroma,
+        }
+    }
+}
+
+// rejects input the analysis can't say anything meaningful about
+fn check_samples(samples: &[f32]) -> Result<(), ChordError> {
+    if samples.is_empty() {
+        return Err(ChordError::EmptyInput);
+    }
+
+    let mut peak = 0.0f32;
+    for &s in samples {
+        if !s.is_finite() {
+            return Err(ChordError::NonFinite);
+        }
+        peak = peak.max(s.abs());
+    }
+
+    if peak < SILENCE_LEVEL {
+        return Err(ChordError::SilentInput);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synth::{ChordSpec, Step, Synth, Timbre, render_chords};
+
+    // a change lands within a hop of where it happens, frames being timed by their centre, and
+    // the padded frames at the end don't leave a stray segment behind. Organ chords sustain, so
+    // neither a decaying chord nor a fresh attack pulls the change towards it
+    #[test]
+    fn segments_change_with_the_chord() {
+        let synth = Synth::default();
+        let chord = |label| {
+            Step::Chord(ChordSpec { timbre: Timbre::Organ, duration: 2.0, ..ChordSpec::from_harte(label).unwrap() })
+        };
+        let (samples, _) = synth.render_progression(&[chord("C:maj"), chord("G:maj")]);
+        let recognizer = ChordRecognizer::new(Config::default());
+        let segments = recognizer.segments(&samples, synth.sample_rate).unwrap();
+
+        let labels: Vec<String> = segments.iter().map(|segment| segment.chord.harte()).collect();
+        assert_eq!(labels, ["C:maj", "G:maj"]);
+        assert_eq!((segments[0].start, segments[1].end), (0.0, 4.0));
+        assert_eq!(segments[0].end, segments[1].start);
+        assert!((segments[1].start - 2.0).abs() < 2048.0 / 44100.0, "change at {}", segments[1].start);
+    }
+
+    // a short chord or gap in the middle of a file used to be all the constant-Q front end heard.
+    // A minor is voiced from the second octave, so its root is the lowest note throughout
+    #[test]
+    fn constant_q_hears_the_whole_file() {
+        let front_end = FrontEnd::ConstantQ { bins_per_octave: 36, min_freq: 65.0, max_freq: 1500.0 };
+        let recognizer = ChordRecognizer::new(Config { front_end, ..Config::default() });
+        let synth = Synth::default();
+        let a_minor = Step::Chord(ChordSpec { octave: 2, duration: 2.0, ..ChordSpec::from_harte("A:min").unwrap() });
+        let f_major = Step::Chord(ChordSpec { duration: 0.3, ..ChordSpec::from_harte("F:maj").unwrap() });
+
+        for (label, middle) in [("F:maj", f_major), ("N", Step::Rest(0.3))] {
+            let (samples, _) = synth.render_progression(&[a_minor.clone(), middle, a_minor.clone()]);
+            let result = recognizer.recognize(&samples, synth.sample_rate).unwrap();
+            assert_eq!(result.harte(), "A:min", "{}", label);
+        }
+    }
+
+    // the A string ringing under an open D chord used to pull in E through its 3rd harmonic,
+    // turning the chord into Dsus2 once suspended chords were in the vocabulary
+    #[test]
+    fn d_chord_survives_overtones() {
+        for vocabulary in [Vocabulary::MajMin, Vocabulary::Triads, Vocabulary::Sevenths] {
+            let recognizer = ChordRecognizer::new(Config { vocabulary, ..Config::default() });
+            let result = recognizer.recognize_path("samples/dchord.wav").unwrap().remove(0);
+
+            let mut top_notes = result.top_notes(3);
+            top_notes.sort();
+            assert_eq!(top_notes, vec![2, 6, 9]);
+
+            assert_eq!(result.root, Some(2));
+            assert_eq!(result.quality.map(|q| q.harte), Some("maj"));
+        }
+    }
+
+    // an inverted triad is reported over its bass note. Voiced from the second octave, so the
+    // lowest note falls within the bass range as it would on a guitar
+    #[test]
+    fn inversions_are_slash_chords() {
+        let synth = Synth::default();
+        let recognizer = ChordRecognizer::new(Config::default());
+
+        for label in ["D:maj/5", "A:min/b3", "C:maj"] {
+            let chord = ChordSpec { octave: 2, ..ChordSpec::from_harte(label).unwrap() };
+            let samples = synth.render(&chord);
+            let result = recognizer.recognize(&samples, synth.sample_rate).unwrap();
+            assert_eq!(result.harte(), label);
+        }
+    }
+
+    // a segment's bass is decided by the frames within it, so the G chord's low notes, heard by
+    // the frames reaching back into it, don't make an A minor over F#
+    #[test]
+    fn segments_keep_their_own_bass() {
+        let (samples, _) = render_chords(&[("C:maj", 2.0), ("G:maj", 2.0), ("A:min", 2.0)]);
+        let segments = ChordRecognizer::new(Config::default()).segments(&samples, 44100).unwrap();
+        let labels: Vec<String> = segments.iter().map(|segment| segment.chord.harte()).collect();
+        assert_eq!(labels, ["C:maj", "G:maj", "A:min"]);
+    }
+
+    // inversions in a progression are reported per segment. Voiced from the second octave so the
+    // bass notes fall within the bass range, and smoothed, as chords that low blur into each other
+    // for a frame around a change
+    #[test]
+    fn segments_are_slash_chords() {
+        let synth = Synth::default();
+        let steps: Vec<Step> = ["C:maj", "G:maj/3", "A:min/b3"].iter()
+            .map(|label| Step::Chord(ChordSpec { octave: 2, duration: 2.0, ..ChordSpec::from_harte(label).unwrap() }))
+            .collect();
+        let (samples, _) = synth.render_progression(&steps);
+
+        let smoothing = Some(Smoothing { self_prob: 0.9, fifths: false });
+        let recognizer = ChordRecognizer::new(Config { smoothing, ..Config::default() });
+        let segments = recognizer.segments(&samples, synth.sample_rate).unwrap();
+        let labels: Vec<String> = segments.iter().map(|segment| segment.chord.harte()).collect();
+        assert_eq!(labels, ["C:maj", "G:maj/3", "A:min/b3"]);
+    }
+
+    // a chord buried in noise is no chord, unless the gate is off and the template match is forced
+    #[test]
+    fn noise_is_gated_to_no_chord() {
+        let synth = Synth::default();
+        let samples = synth.render(&ChordSpec { noise: 10.0, ..ChordSpec::default() });
+
+        let gated = ChordRecognizer::new(Config::default());
+        assert_eq!(gated.recognize(&samples, synth.sample_rate).unwrap().harte(), "N");
+
+        let ungated = ChordRecognizer::new(Config { gate: None, ..Config::default() });
+        assert_ne!(ungated.recognize(&samples, synth.sample_rate).unwrap().harte(), "N");
+    }
+
+    // candidates are the most likely chords in order, their probabilities spread over every chord
+    #[test]
+    fn candidates_are_ranked_by_probability() {
+        let synth = Synth::default();
+        let samples = synth.render(&ChordSpec::from_harte("A:min").unwrap());
+
+        let all = ChordRecognizer::new(Config { candidates: usize::MAX, ..Config::default() })
+            .recognize(&samples, synth.sample_rate)
+            .unwrap();
+        let total: f32 = all.candidates.iter().map(|candidate| candidate.probability).sum();
+        assert!((total - 1.0).abs() < 1e-4, "{}", total);
+        assert!(all.candidates.windows(2).all(|pair| pair[0].probability >= pair[1].probability));
+        assert_eq!(all.candidates[0].harte(), "A:min");
+        assert_eq!(all.candidates[0].probability, all.probability);
+
+        let top = ChordRecognizer::new(Config { candidates: 2, ..Config::default() })
+            .recognize(&samples, synth.sample_rate)
+            .unwrap();
+        assert_eq!(top.candidates, all.candidates[..2]);
+    }
+
+    // chords the gate rules out have no chance, which leaves no chord the only candidate
+    #[test]
+    fn gated_chords_are_not_candidates() {
+        let synth = Synth::default();
+        let samples = synth.render(&ChordSpec { noise: 10.0, ..ChordSpec::default() });
+
+        let result = ChordRecognizer::new(Config::default()).recognize(&samples, synth.sample_rate).unwrap();
+        let candidates: Vec<String> = result.candidates.iter().map(|candidate| candidate.harte()).collect();
+        assert_eq!(candidates, ["N"]);
+        assert_eq!(result.candidates[0].probability, 1.0);
+    }
+
+    // a chord dying away into a rest is gated by what the window hears, not by the frame's tail
+    #[test]
+    fn rests_between_chords_are_no_chord() {
+        let (samples, _) = render_chords(&[("C:maj", 1.0), ("N", 1.0), ("G:maj", 1.0)]);
+        let segments = ChordRecognizer::new(Config::default()).segments(&samples, 44100).unwrap();
+        let labels: Vec<String> = segments.iter().map(|segment| segment.chord.harte()).collect();
+        assert_eq!(labels, ["C:maj", "N", "G:maj"]);
+    }
+}
@@ -0,0 +1,189 @@
+//! Scoring of chroma vectors against chord templates.
+//!
+//! Templates hold equal weight on every chord tone and are scaled to unit length, so triads,
+//! sevenths and fuller chords start out on an equal footing. Higher scores are always better,
+//! distances are negated.
+
+use std::fmt::Debug;
+
+// KL divergence needs every pitch class to have some probability under the template,
+// otherwise any energy outside the chord tones makes it infinite
+const KL_SMOOTHING: f32 = 0.02;
+
+/// How well a chroma vector matches a chord template.
+pub trait Similarity: Debug + Send + Sync {
+    /// Similarity of `chroma` to `template`, higher being a better match. Chroma is
+    /// max-normalized and templates have unit length.
+    fn score(&self, chroma: &[f32; 12], template: &[f32; 12]) -> f32;
+
+    /// Score no chord (N) gets. Chords have to beat it to be reported.
+    fn no_chord_score(&self) -> f32;
+}
+
+/// The similarity measures that come with the crate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Metric {
+    Dot,
+    Cosine,
+    Pearson,
+    Euclidean,
+    KullbackLeibler,
+}
+
+impl Metric {
+    /// Parses `dot`, `cosine`, `pearson`, `euclidean` or `kl`.
+    pub fn parse(name: &str) -> Option<Metric> {
+        match name {
+            "dot" => Some(Metric::Dot),
+            "cosine" => Some(Metric::Cosine),
+            "pearson" => Some(Metric::Pearson),
+            "euclidean" => Some(Metric::Euclidean),
+            "kl" => Some(Metric::KullbackLeibler),
+            _ => None,
+        }
+    }
+
+    pub fn similarity(self) -> Box<dyn Similarity> {
+        match self {
+            Metric::Dot => Box::new(Dot),
+            Metric::Cosine => Box::new(Cosine),
+            Metric::Pearson => Box::new(Pearson),
+            Metric::Euclidean => Box::new(Euclidean),
+            Metric::KullbackLeibler => Box::new(KullbackLeibler),
+        }
+    }
+}
+
+/// Plain dot product. Fuller chroma scores higher against every template, so noise can't be told
+/// apart from chords by score alone and is left to the gate.
+#[derive(Clone, Copy, Debug)]
+pub struct Dot;
+
+impl Similarity for Dot {
+    fn score(&self, chroma: &[f32; 12], template: &[f32; 12]) -> f32 {
+        dot(chroma, template)
+    }
+
+    fn no_chord_score(&self) -> f32 {
+        0.25
+    }
+}
+
+/// Cosine of the angle between chroma and template, from 0 to 1 for non-negative chroma.
+#[derive(Clone, Copy, Debug)]
+pub struct Cosine;
+
+impl Similarity for Cosine {
+    fn score(&self, chroma: &[f32; 12], template: &[f32; 12]) -> f32 {
+        let norm = dot(chroma, chroma).sqrt() * dot(template, template).sqrt();
+        if norm > 0.0 { dot(chroma, template) / norm } else { 0.0 }
+    }
+
+    fn no_chord_score(&self) -> f32 {
+        // flat chroma scores 0.5 against any triad, noise lands around 0.6
+        0.7
+    }
+}
+
+/// Pearson correlation, cosine similarity after removing the mean of each vector.
+#[derive(Clone, Copy, Debug)]
+pub struct Pearson;
+
+impl Similarity for Pearson {
+    fn score(&self, chroma: &[f32; 12], template: &[f32; 12]) -> f32 {
+        let centered = |v: &[f32; 12]| {
+            let mean = v.iter().sum::<f32>() / 12.0;
+            v.map(|x| x - mean)
+        };
+        Cosine.score(&centered(chroma), &centered(template))
+    }
+
+    fn no_chord_score(&self) -> f32 {
+        0.5
+    }
+}
+
+/// Negated Euclidean distance between the unit-length chroma and the template.
+#[derive(Clone, Copy, Debug)]
+pub struct Euclidean;
+
+impl Similarity for Euclidean {
+    fn score(&self, chroma: &[f32; 12], template: &[f32; 12]) -> f32 {
+        let norm = dot(chroma, chroma).sqrt();
+        if norm == 0.0 {
+            return -1.0;
+        }
+
+        let distance: f32 = chroma.iter()
+            .zip(template)
+            .map(|(c, t)| (c / norm - t).powi(2))
+            .sum::<f32>()
+            .sqrt();
+        -distance
+    }
+
+    fn no_chord_score(&self) -> f32 {
+        // the distance at which Cosine reaches its threshold, as |a - b|² = 2 - 2 cos(a, b)
+        -0.77
+    }
+}
+
+/// Negated Kullback-Leibler divergence of the chroma from the template, both treated as
+/// probability distributions over pitch classes.
+#[derive(Clone, Copy, Debug)]
+pub struct KullbackLeibler;
+
+impl Similarity for KullbackLeibler {
+    fn score(&self, chroma: &[f32; 12], template: &[f32; 12]) -> f32 {
+        let chroma_sum: f32 = chroma.iter().sum();
+        if chroma_sum <= 0.0 {
+            return self.no_chord_score() - 1.0;
+        }
+        let template_sum: f32 = template.iter().map(|t| t + KL_SMOOTHING).sum();
+
+        let divergence: f32 = chroma.iter()
+            .zip(template)
+            .map(|(c, t)| {
+                let p = c / chroma_sum;
+                let q = (t + KL_SMOOTHING) / template_sum;
+                if p > 0.0 { p * (p / q).ln() } else { 0.0 }
+            })
+            .sum();
+        -divergence
+    }
+
+    fn no_chord_score(&self) -> f32 {
+        -0.9
+    }
+}
+
+fn dot(a: &[f32; 12], b: &[f32; 12]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recognizer::{ChordRecognizer, Config};
+    use crate::synth::{ChordSpec, Synth, Timbre};
+    use crate::vocabulary::Vocabulary;
+
+    const METRICS: [Metric; 5] =
+        [Metric::Dot, Metric::Cosine, Metric::Pearson, Metric::Euclidean, Metric::KullbackLeibler];
+
+    // triads and sevenths come back right whichever way they're scored
+    #[test]
+    fn every_metric_recognizes_synthesized_chords() {
+        let synth = Synth::default();
+
+        for metric in METRICS {
+            let recognizer = ChordRecognizer::new(Config { metric, vocabulary: Vocabulary::Sevenths, ..Config::default() });
+            for label in ["A:min", "D:maj", "F:maj7", "G:7", "E:min7"] {
+                let chord = ChordSpec { octave: 2, timbre: Timbre::Piano, ..ChordSpec::from_harte(label).unwrap() };
+                let result = recognizer.recognize(&synth.render(&chord), synth.sample_rate).unwrap();
+
+                assert_eq!(result.harte(), label, "{:?}", metric);
+            }
+        }
+    }
+}
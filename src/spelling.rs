@@ -0,0 +1,213 @@
+//! Spelling of pitch classes as note names, with sharps or flats to suit the key.
+//!
+//! ```
+//! use chord::key::Key;
+//! use chord::spelling::Spelling;
+//! use chord::QUALITIES;
+//!
+//! let spelling = Spelling::Key(Key::parse("Eb major").unwrap());
+//! let seventh = QUALITIES.iter().find(|q| q.harte == "7").unwrap();
+//! assert_eq!(spelling.chord_tones(10, seventh), ["Bb", "D", "F", "Ab"]);
+//! ```
+
+use crate::chords::NOTE_NAMES;
+use crate::key::{Key, Mode};
+use crate::vocabulary::Quality;
+
+const LETTERS: [char; 7] = ['C', 'D', 'E', 'F', 'G', 'A', 'B'];
+
+// pitch class of each natural note
+const NATURALS: [usize; 7] = [0, 2, 4, 5, 7, 9, 11];
+
+// letter and accidental of the tonic of every major and minor key, by pitch class. Keys with six
+// sharps or flats could go either way and take the more common spelling
+const MAJOR_TONICS: [(usize, i32); 12] = [
+    (0, 0), (1, -1), (1, 0), (2, -1), (2, 0), (3, 0),
+    (3, 1), (4, 0), (5, -1), (5, 0), (6, -1), (6, 0),
+];
+const MINOR_TONICS: [(usize, i32); 12] = [
+    (0, 0), (0, 1), (1, 0), (2, -1), (2, 0), (3, 0),
+    (3, 1), (4, 0), (4, 1), (5, 0), (6, -1), (6, 0),
+];
+
+// letters from the tonic up to each pitch class by semitones above it, so roots outside the
+// scale are spelled like their Roman numerals: bIII, #IV, bVI, bVII
+const DEGREE_STEPS: [usize; 12] = [0, 1, 1, 2, 2, 3, 3, 4, 5, 5, 6, 6];
+
+// the notes of major and natural minor keys, in semitones above the tonic
+const MAJOR_SCALE: [usize; 7] = [0, 2, 4, 5, 7, 9, 11];
+const MINOR_SCALE: [usize; 7] = [0, 2, 3, 5, 7, 8, 10];
+
+// letters from a root up to its chord tones, the tritone being a diminished fifth in chords
+const CHORD_STEPS: [usize; 12] = [0, 1, 1, 2, 2, 3, 4, 4, 5, 5, 6, 6];
+
+/// How pitch classes are named.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Spelling {
+    /// Always sharps, as in [`NOTE_NAMES`](crate::NOTE_NAMES), for when the key isn't known.
+    #[default]
+    Sharps,
+    /// As scale degrees of the key, Bb rather than A# in F major. Chord tones are spelled up
+    /// from their root by letter, so Bb:7 is Bb D F Ab.
+    Key(Key),
+}
+
+// a letter, 0 being C, with sharps counted up and flats down
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Note {
+    letter: usize,
+    accidental: i32,
+}
+
+impl Note {
+    // the note `steps` letters above this one that has pitch class `pc`
+    fn above(self, steps: usize, pc: usize) -> Note {
+        let letter = (self.letter + steps) % 7;
+        let shift = (pc as i32 - NATURALS[letter] as i32).rem_euclid(12);
+        let accidental = if shift > 6 { shift - 12 } else { shift };
+        Note { letter, accidental }
+    }
+
+    fn name(self) -> String {
+        let accidental = if self.accidental >= 0 { "#" } else { "b" };
+        format!("{}{}", LETTERS[self.letter], accidental.repeat(self.accidental.unsigned_abs() as usize))
+    }
+}
+
+impl Spelling {
+    /// Name of the pitch class `pc`, 0 being C.
+    pub fn note(&self, pc: usize) -> String {
+        match self.spell(pc) {
+            Some(note) => note.name(),
+            None => NOTE_NAMES[pc % 12].to_string(),
+        }
+    }
+
+    /// Name of the pitch class `pc` heard on its own rather than as a chord tone. Notes of the
+    /// key are spelled as scale degrees and the others as naturals where they can be, or with
+    /// the sharps or flats of the key, so E rather than Fb in Eb major.
+    pub fn pitch_class(&self, pc: usize) -> String {
+        let (Spelling::Key(key), Some(note)) = (self, self.spell(pc)) else {
+            return self.note(pc);
+        };
+        let scale = match key.mode {
+            Mode::Major => MAJOR_SCALE,
+            Mode::Minor => MINOR_SCALE,
+        };
+        if scale.contains(&((pc + 12 - key.tonic) % 12)) {
+            return note.name();
+        }
+        if let Some(letter) = NATURALS.iter().position(|&natural| natural == pc % 12) {
+            return Note { letter, accidental: 0 }.name();
+        }
+
+        // the sharps or flats of the key signature, none in C major and A minor, which keep the
+        // accidentals of the Roman numerals
+        let signature: i32 = scale.iter()
+            .filter_map(|degree| self.spell((key.tonic + degree) % 12))
+            .map(|note| note.accidental)
+            .sum();
+        let (letter, accidental) = match signature.signum() {
+            1 => ((pc + 11) % 12, 1),
+            -1 => ((pc + 1) % 12, -1),
+            _ => return note.name(),
+        };
+        let letter = NATURALS.iter().position(|&natural| natural == letter).unwrap();
+        Note { letter, accidental }.name()
+    }
+
+    /// Names of the chord tones of `quality` on `root`, root first.
+    pub fn chord_tones(&self, root: usize, quality: &Quality) -> Vec<String> {
+        quality.intervals.iter().map(|&interval| self.chord_tone(root, quality, interval)).collect()
+    }
+
+    /// Name of the bass note `bass` under a chord, spelled as a chord tone when it is one.
+    pub fn bass(&self, root: usize, quality: &Quality, bass: usize) -> String {
+        let interval = (bass + 12 - root) % 12;
+        if quality.intervals.contains(&interval) {
+            self.chord_tone(root, quality, interval)
+        } else {
+            self.note(bass)
+        }
+    }
+
+    fn chord_tone(&self, root: usize, quality: &Quality, interval: usize) -> String {
+        let pc = (root + interval) % 12;
+        match self.spell(root) {
+            Some(root) => root.above(letter_steps(quality, interval), pc).name(),
+            None => NOTE_NAMES[pc].to_string(),
+        }
+    }
+
+    fn spell(&self, pc: usize) -> Option<Note> {
+        let Spelling::Key(key) = self else {
+            return None;
+        };
+        let (letter, accidental) = match key.mode {
+            Mode::Major => MAJOR_TONICS[key.tonic],
+            Mode::Minor => MINOR_TONICS[key.tonic],
+        };
+
+        let degree = (pc + 12 - key.tonic) % 12;
+        Some(Note { letter, accidental }.above(DEGREE_STEPS[degree], pc))
+    }
+}
+
+// fifths and sevenths stay fifths and sevenths even when altered, as in the augmented fifth of
+// C:aug (G#) or the diminished seventh of C:dim7 (Bbb)
+fn letter_steps(quality: &Quality, interval: usize) -> usize {
+    match (quality.harte, interval) {
+        ("aug", 8) => 4,
+        ("dim7", 9) => 6,
+        _ => CHORD_STEPS[interval],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vocabulary::QUALITIES;
+
+    fn quality(harte: &str) -> &'static Quality {
+        QUALITIES.iter().find(|q| q.harte == harte).unwrap()
+    }
+
+    #[test]
+    fn spells_in_key() {
+        let e_flat = Spelling::Key(Key::parse("Eb major").unwrap());
+        let names: Vec<String> = [3, 8, 10, 1, 6].into_iter().map(|pc| e_flat.note(pc)).collect();
+        assert_eq!(names, ["Eb", "Ab", "Bb", "Db", "Gb"]);
+        assert_eq!(e_flat.chord_tones(10, quality("7")), ["Bb", "D", "F", "Ab"]);
+        assert_eq!(e_flat.bass(3, quality("maj"), 10), "Bb");
+
+        let a_minor = Spelling::Key(Key::parse("A minor").unwrap());
+        assert_eq!(a_minor.chord_tones(4, quality("7")), ["E", "G#", "B", "D"]);
+        assert_eq!(a_minor.chord_tones(8, quality("dim7")), ["G#", "B", "D", "F"]);
+
+        let c_major = Spelling::Key(Key::parse("C major").unwrap());
+        assert_eq!(c_major.chord_tones(0, quality("aug")), ["C", "E", "G#"]);
+        assert_eq!(c_major.chord_tones(0, quality("dim7")), ["C", "Eb", "Gb", "Bbb"]);
+
+        assert_eq!(Spelling::Sharps.chord_tones(10, quality("7")), ["A#", "D", "F", "G#"]);
+    }
+
+    // pitch classes on their own agree with the chord tones they would be, whatever the degree
+    #[test]
+    fn spells_pitch_classes_in_key() {
+        let e_flat = Spelling::Key(Key::parse("Eb major").unwrap());
+        let names: Vec<String> = (0..12).map(|pc| e_flat.pitch_class(pc)).collect();
+        assert_eq!(names, ["C", "Db", "D", "Eb", "E", "F", "Gb", "G", "Ab", "A", "Bb", "B"]);
+        assert_eq!(e_flat.chord_tones(0, quality("maj")), ["C", "E", "G"]);
+        assert_eq!(e_flat.note(4), "Fb");
+
+        let d_major = Spelling::Key(Key::parse("D major").unwrap());
+        let names: Vec<String> = [1, 6, 8, 10, 3].into_iter().map(|pc| d_major.pitch_class(pc)).collect();
+        assert_eq!(names, ["C#", "F#", "G#", "A#", "D#"]);
+
+        let c_major = Spelling::Key(Key::parse("C major").unwrap());
+        let names: Vec<String> = [1, 3, 6, 8, 10].into_iter().map(|pc| c_major.pitch_class(pc)).collect();
+        assert_eq!(names, ["Db", "Eb", "F#", "Ab", "Bb"]);
+
+        assert_eq!(Spelling::Sharps.pitch_class(10), "A#");
+    }
+}
@@ -0,0 +1,61 @@
+// short-time Fourier transform of a whole signal and its inverse, for the analyses that look at
+// how the spectrum changes over time rather than at one block
+
+use rustfft::{FftPlanner, num_complex::Complex};
+
+use crate::chroma::hann_window;
+
+// Hann windowed spectra of frames centred every `hop` samples, zero padded past either end.
+// Every spectrum holds all `frame_size` bins
+pub(crate) fn stft(samples: &[f32], frame_size: usize, hop: usize) -> Vec<Vec<Complex<f32>>> {
+    let window = hann_window(frame_size);
+    let mut planner = FftPlanner::<f32>::new();
+    let fft = planner.plan_fft_forward(frame_size);
+
+    (0..samples.len())
+        .step_by(hop)
+        .map(|centre| {
+            let mut input: Vec<Complex<f32>> = (0..frame_size)
+                .map(|j| {
+                    let sample = (centre + j).checked_sub(frame_size / 2).and_then(|t| samples.get(t));
+                    Complex { re: sample.copied().unwrap_or(0.0) * window[j], im: 0.0 }
+                })
+                .collect();
+            fft.process(&mut input);
+            input
+        })
+        .collect()
+}
+
+// `len` samples back from spectra laid out like those of stft, overlap-added through a second
+// Hann window and divided by the summed squared windows, so an unchanged STFT gives the signal back
+pub(crate) fn istft(spectra: &[Vec<Complex<f32>>], frame_size: usize, hop: usize, len: usize) -> Vec<f32> {
+    let window = hann_window(frame_size);
+    let mut planner = FftPlanner::<f32>::new();
+    let ifft = planner.plan_fft_inverse(frame_size);
+
+    let mut samples = vec![0.0f32; len];
+    let mut weights = vec![0.0f32; len];
+
+    for (i, spectrum) in spectra.iter().enumerate() {
+        let mut frame = spectrum.clone();
+        ifft.process(&mut frame);
+
+        for (j, (value, w)) in frame.iter().zip(window.iter()).enumerate() {
+            let Some(t) = (i * hop + j).checked_sub(frame_size / 2).filter(|&t| t < len) else {
+                continue;
+            };
+            // rustfft leaves the inverse unscaled
+            samples[t] += value.re / frame_size as f32 * w;
+            weights[t] += w * w;
+        }
+    }
+
+    for (sample, weight) in samples.iter_mut().zip(weights) {
+        if weight > 1e-6 {
+            *sample /= weight;
+        }
+    }
+
+    samples
+}
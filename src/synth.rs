@@ -0,0 +1,324 @@
+//! Additive synthesis of chords and chord progressions, for building labeled test audio offline.
+//!
+//! ```no_run
+//! use chord::synth::{ChordSpec, Step, Synth, Timbre};
+//!
+//! let synth = Synth::default();
+//! let a_minor = ChordSpec::from_harte("A:min/b3")?;
+//! let (samples, labels) = synth.render_progression(&[
+//!     Step::Chord(ChordSpec { timbre: Timbre::Piano, duration: 2.0, ..a_minor }),
+//!     Step::Rest(0.5),
+//! ]);
+//! chord::write_wav("a_minor.wav", &samples, synth.sample_rate)?;
+//! chord::lab::save_lab("a_minor.lab", &labels)?;
+//! # Ok::<(), chord::ChordError>(())
+//! ```
+
+use std::f32::consts::PI;
+
+use crate::chords::harte_label;
+use crate::error::ChordError;
+use crate::harte::{self, Label};
+use crate::lab::Annotation;
+use crate::spelling::Spelling;
+use crate::vocabulary::{QUALITIES, Quality};
+
+// rendered chords peak at this level before noise is added, leaving headroom for it
+const PEAK: f32 = 0.5;
+
+// fade in and out so chords don't start or end with a click, in seconds
+const FADE: f32 = 0.005;
+
+/// The harmonic make-up and decay of a synthesized note.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Timbre {
+    /// A pure tone.
+    Sine,
+    /// Bright, plucked and decaying.
+    Guitar,
+    /// Darker than the guitar, struck and decaying.
+    Piano,
+    /// Sustained, with strong low harmonics.
+    Organ,
+    /// Amplitude of each harmonic, the fundamental first, and the decay rate per second.
+    Custom { harmonics: Vec<f32>, decay: f32 },
+}
+
+impl Timbre {
+    /// Parses `sine`, `guitar`, `piano` or `organ`.
+    pub fn parse(name: &str) -> Option<Timbre> {
+        match name {
+            "sine" => Some(Timbre::Sine),
+            "guitar" => Some(Timbre::Guitar),
+            "piano" => Some(Timbre::Piano),
+            "organ" => Some(Timbre::Organ),
+            _ => None,
+        }
+    }
+
+    // amplitude of each harmonic, the fundamental first
+    fn harmonics(&self) -> Vec<f32> {
+        match self {
+            Timbre::Sine => vec![1.0],
+            Timbre::Guitar => (1..=10).map(|h| 1.0 / h as f32).collect(),
+            Timbre::Piano => (1..=8).map(|h| 1.0 / (h as f32).powf(1.5)).collect(),
+            Timbre::Organ => (1..=8).map(|h| 1.0 / (h as f32).powf(0.7)).collect(),
+            Timbre::Custom { harmonics, .. } => harmonics.clone(),
+        }
+    }
+
+    // exponential decay rate of the fundamental per second, higher harmonics die out faster
+    fn decay(&self) -> f32 {
+        match self {
+            Timbre::Sine | Timbre::Organ => 0.0,
+            Timbre::Guitar => 1.5,
+            Timbre::Piano => 1.0,
+            Timbre::Custom { decay, .. } => *decay,
+        }
+    }
+}
+
+/// A chord to synthesize.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChordSpec {
+    /// Pitch class of the root, 0 being C.
+    pub root: usize,
+    pub quality: &'static Quality,
+    /// How many of the lowest chord tones move up an octave, 1 putting the third in the bass.
+    pub inversion: usize,
+    /// Octave of the root in scientific pitch notation, C4 being middle C.
+    pub octave: i32,
+    pub timbre: Timbre,
+    /// Every note is mistuned by a random amount up to this many cents either way.
+    pub detune_cents: f32,
+    /// Level of white noise added, relative to the peak of the chord.
+    pub noise: f32,
+    /// Length in seconds.
+    pub duration: f32,
+}
+
+impl Default for ChordSpec {
+    fn default() -> ChordSpec {
+        ChordSpec {
+            root: 0,
+            quality: &QUALITIES[0],
+            inversion: 0,
+            octave: 3,
+            timbre: Timbre::Guitar,
+            detune_cents: 0.0,
+            noise: 0.0,
+            duration: 1.0,
+        }
+    }
+}
+
+impl ChordSpec {
+    /// A chord from a Harte label such as `A:min/b3`, with the other settings left at their
+    /// defaults. The label has to describe one of [`QUALITIES`], with the bass on a chord tone.
+    pub fn from_harte(label: &str) -> Result<ChordSpec, ChordError> {
+        let unsupported = || ChordError::InvalidAnnotation(format!("can't synthesize {:?}", label));
+
+        let Label::Chord { root, intervals, bass } = harte::parse(label).map_err(ChordError::InvalidAnnotation)?
+        else {
+            return Err(unsupported());
+        };
+        let quality = QUALITIES.iter()
+            .find(|q| q.intervals.iter().fold(0u16, |bits, &i| bits | 1 << i) == intervals)
+            .ok_or_else(unsupported)?;
+        let inversion = quality.intervals.iter().position(|&i| i == bass).ok_or_else(unsupported)?;
+
+        Ok(ChordSpec { root, quality, inversion, ..ChordSpec::default() })
+    }
+
+    /// Harte label of the chord, e.g. `C:maj/3` for the first inversion of C major.
+    pub fn harte(&self) -> String {
+        harte_label(Some((self.root, self.quality)), self.bass(), Spelling::Sharps)
+    }
+
+    /// MIDI note numbers of the chord tones, lowest first.
+    pub fn midi_notes(&self) -> Vec<i32> {
+        let root_note = 12 * (self.octave + 1) + self.root as i32;
+        let inversion = self.inversion % self.quality.intervals.len();
+
+        let mut notes: Vec<i32> = self.quality.intervals.iter().enumerate()
+            .map(|(i, &interval)| root_note + interval as i32 + if i < inversion { 12 } else { 0 })
+            .collect();
+        notes.sort();
+        notes
+    }
+
+    // pitch class of the bass note when it isn't the root
+    fn bass(&self) -> Option<usize> {
+        let inversion = self.inversion % self.quality.intervals.len();
+        (inversion > 0).then(|| (self.root + self.quality.intervals[inversion]) % 12)
+    }
+}
+
+/// One step of a progression.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Step {
+    Chord(ChordSpec),
+    /// Silence for this many seconds, labeled `N`.
+    Rest(f32),
+}
+
+/// Renders chords as mono samples. The same seed always gives the same audio.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Synth {
+    pub sample_rate: usize,
+    pub seed: u64,
+}
+
+impl Default for Synth {
+    fn default() -> Synth {
+        Synth { sample_rate: 44100, seed: 0 }
+    }
+}
+
+impl Synth {
+    pub fn render(&self, chord: &ChordSpec) -> Vec<f32> {
+        self.render_seeded(chord, self.seed)
+    }
+
+    /// Renders the steps back to back, along with a `.lab` annotation of where each chord sits.
+    pub fn render_progression(&self, steps: &[Step]) -> (Vec<f32>, Vec<Annotation>) {
+        let mut samples = Vec::new();
+        let mut labels = Vec::new();
+
+        for (i, step) in steps.iter().enumerate() {
+            let start = samples.len() as f32 / self.sample_rate as f32;
+            let label = match step {
+                Step::Chord(chord) => {
+                    // every chord gets its own phases, detuning and noise
+                    samples.extend(self.render_seeded(chord, self.seed.wrapping_add(i as u64)));
+                    chord.harte()
+                }
+                Step::Rest(duration) => {
+                    samples.resize(samples.len() + self.n_samples(*duration), 0.0);
+                    "N".to_string()
+                }
+            };
+            let end = samples.len() as f32 / self.sample_rate as f32;
+            labels.push(Annotation { start, end, label });
+        }
+
+        (samples, labels)
+    }
+
+    fn render_seeded(&self, chord: &ChordSpec, seed: u64) -> Vec<f32> {
+        let sr = self.sample_rate as f32;
+        let mut rng = Rng::new(seed);
+        let mut samples = vec![0.0f32; self.n_samples(chord.duration)];
+
+        let harmonics = chord.timbre.harmonics();
+        let decay = chord.timbre.decay();
+
+        for note in chord.midi_notes() {
+            let cents = chord.detune_cents * rng.next();
+            let freq = 440.0 * 2f32.powf((note as f32 - 69.0 + cents / 100.0) / 12.0);
+
+            for (h, &amplitude) in harmonics.iter().enumerate() {
+                let harmonic_freq = freq * (h + 1) as f32;
+                if harmonic_freq >= sr / 2.0 {
+                    break;
+                }
+                let phase = PI * rng.next();
+                let harmonic_decay = decay * (1.0 + 0.3 * h as f32);
+
+                for (t, sample) in samples.iter_mut().enumerate() {
+                    let time = t as f32 / sr;
+                    let envelope = (-harmonic_decay * time).exp();
+                    *sample += amplitude * envelope * (2.0 * PI * harmonic_freq * time + phase).sin();
+                }
+            }
+        }
+
+        let peak = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        let scale = if peak > 0.0 { PEAK / peak } else { 0.0 };
+        for sample in samples.iter_mut() {
+            *sample = *sample * scale + chord.noise * PEAK * rng.next();
+        }
+
+        let fade = self.n_samples(FADE).min(samples.len() / 2);
+        let n = samples.len();
+        for i in 0..fade {
+            let gain = i as f32 / fade as f32;
+            samples[i] *= gain;
+            samples[n - 1 - i] *= gain;
+        }
+
+        samples
+    }
+
+    fn n_samples(&self, seconds: f32) -> usize {
+        (seconds.max(0.0) * self.sample_rate as f32).round() as usize
+    }
+}
+
+// test audio of `(label, seconds)` pairs back to back from the default synth, `N` being a rest,
+// along with where each chord sits
+#[cfg(test)]
+pub(crate) fn render_chords(chords: &[(&str, f32)]) -> (Vec<f32>, Vec<Annotation>) {
+    let steps: Vec<Step> = chords.iter()
+        .map(|&(label, duration)| match label {
+            "N" => Step::Rest(duration),
+            label => Step::Chord(ChordSpec { duration, ..ChordSpec::from_harte(label).unwrap() }),
+        })
+        .collect();
+
+    Synth::default().render_progression(&steps)
+}
+
+// splitmix64, plenty random for phases and noise and keeps the crate free of dependencies
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng(seed)
+    }
+
+    // uniform in [-1, 1)
+    fn next(&mut self) -> f32 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+
+        (z >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recognizer::{ChordRecognizer, Config};
+
+    // every major and minor chord, in every inversion, comes back with its root, quality and bass.
+    // rooted in octave 2 so the bass note stays within the range bass detection listens to
+    #[test]
+    fn recognizes_synthesized_triads() {
+        let synth = Synth::default();
+        let recognizer = ChordRecognizer::new(Config::default());
+
+        for root in 0..12 {
+            for quality in &QUALITIES[..2] {
+                for inversion in 0..3 {
+                    let chord = ChordSpec {
+                        root,
+                        quality,
+                        inversion,
+                        octave: 2,
+                        detune_cents: 5.0,
+                        noise: 0.01,
+                        duration: 0.5,
+                        ..ChordSpec::default()
+                    };
+                    let result = recognizer.recognize(&synth.render(&chord), synth.sample_rate).unwrap();
+
+                    assert_eq!(result.harte(), chord.harte());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,113 @@
+// global tuning estimation: how far, in cents, the notes of a recording sit from A4 = 440 Hz
+
+use std::f32::consts::PI;
+use std::ops::RangeInclusive;
+
+use rustfft::{FftPlanner, num_complex::Complex};
+
+use crate::chroma::{TREBLE_RANGE, freq_to_midi, hann_window, spectral_peaks};
+
+pub(crate) const STANDARD_A4: f32 = 440.0;
+
+/// Frequencies of A4 that are accepted as a reference, wide enough for baroque and other
+/// historical pitches a semitone or so away from 440 Hz.
+pub const REFERENCE_RANGE: RangeInclusive<f32> = 400.0..=480.0;
+
+// tuning is estimated from frames of this many samples, long enough to resolve a few cents
+// once peaks are interpolated, and only from peaks reaching this fraction of the frame's strongest
+const TUNING_FRAME_SIZE: usize = 16384;
+const TUNING_FLOOR: f32 = 0.05;
+
+// magnitude-weighted circular mean of how far each spectral peak is from its nearest semitone.
+// `peaks` are (fractional MIDI note number against A4 = 440 Hz, magnitude).
+// deviations wrap around at half a semitone, so averaging them as angles keeps a recording
+// tuned 49 cents sharp from averaging out with one 49 cents flat.
+// returns a value in [-50, 50), 0 when there are no peaks
+fn deviation_cents(peaks: &[(f32, f32)]) -> f32 {
+    let (mut re, mut im) = (0.0f32, 0.0f32);
+
+    for &(midi, mag) in peaks {
+        let angle = 2.0 * PI * (midi - midi.round());
+        re += mag * angle.cos();
+        im += mag * angle.sin();
+    }
+
+    if re == 0.0 && im == 0.0 {
+        return 0.0;
+    }
+
+    let cents = 100.0 * im.atan2(re) / (2.0 * PI);
+    if cents >= 50.0 { cents - 100.0 } else { cents }
+}
+
+/// Frequency of A4 for a tuning `cents` away from 440 Hz.
+pub fn reference_hz(cents: f32) -> f32 {
+    STANDARD_A4 * 2f32.powf(cents / 1200.0)
+}
+
+/// How many cents a given A4 frequency is away from 440 Hz.
+pub fn cents_from(reference_hz: f32) -> f32 {
+    1200.0 * (reference_hz / STANDARD_A4).log2()
+}
+
+// estimated tuning of a recording in cents away from A4 = 440 Hz, from the spectral peaks
+// of non-overlapping frames across the whole file
+pub(crate) fn estimate_tuning(samples: &[f32], sr: usize) -> f32 {
+    let frame_size = TUNING_FRAME_SIZE.min(samples.len());
+    if frame_size < 3 {
+        return 0.0;
+    }
+
+    let window = hann_window(frame_size);
+    let mut planner = FftPlanner::<f32>::new();
+    let fft = planner.plan_fft_forward(frame_size);
+
+    let mut peaks = Vec::new();
+    for frame in samples.chunks_exact(frame_size) {
+        let mut input: Vec<Complex<f32>> = frame.iter()
+            .zip(window.iter())
+            .map(|(s,w)| Complex{ re: s*w, im: 0.0 })
+            .collect();
+        fft.process(&mut input);
+
+        let mags: Vec<f32> = input[..frame_size/2].iter().map(|c| c.norm()).collect();
+        let floor = TUNING_FLOOR * mags.iter().cloned().fold(0.0, f32::max);
+
+        peaks.extend(
+            spectral_peaks(&mags, sr, frame_size, floor)
+                .into_iter()
+                .filter(|(freq, _)| TREBLE_RANGE.contains(freq))
+                .map(|(freq, mag)| (freq_to_midi(freq, STANDARD_A4), mag)),
+        );
+    }
+
+    deviation_cents(&peaks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ChordError;
+    use crate::recognizer::{ChordRecognizer, Config};
+    use crate::synth::{ChordSpec, Synth};
+
+    // rendered at one sample rate and analyzed at another, every frequency is off by their ratio
+    #[test]
+    fn recovers_detuning() {
+        let chord = ChordSpec { duration: 2.0, ..ChordSpec::from_harte("C:maj").unwrap() };
+        for a4 in [432.0, 440.0, 446.0] {
+            let synth = Synth { sample_rate: (44100.0 * STANDARD_A4 / a4).round() as usize, ..Synth::default() };
+            let estimate = reference_hz(estimate_tuning(&synth.render(&chord), 44100));
+            assert!((estimate - a4).abs() < 0.5, "{} Hz estimated as {} Hz", a4, estimate);
+        }
+    }
+
+    #[test]
+    fn rejects_references_out_of_range() {
+        let samples = Synth::default().render(&ChordSpec::default());
+        for hz in [10.0, 399.0, 481.0, f32::NAN] {
+            let recognizer = ChordRecognizer::new(Config { reference_hz: Some(hz), ..Config::default() });
+            assert!(matches!(recognizer.recognize(&samples, 44100), Err(ChordError::InvalidReference(_))));
+        }
+    }
+}
@@ -0,0 +1,123 @@
+// chord qualities the matcher can choose from, all rooted on C and rolled into place later
+
+/// How many chord qualities take part in matching, each level includes the ones before it.
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug)]
+pub enum Vocabulary {
+    MajMin,
+    Triads,
+    Sevenths,
+    Full,
+}
+
+/// A chord quality, independent of its root.
+#[derive(Debug, PartialEq)]
+pub struct Quality {
+    /// Human readable, e.g. "dominant 7th".
+    pub name: &'static str,
+    /// Harte shorthand used after the root, e.g. "7" in G:7.
+    pub harte: &'static str,
+    /// Semitones above the root, root included.
+    pub intervals: &'static [usize],
+    /// Smallest vocabulary the quality belongs to.
+    pub vocabulary: Vocabulary,
+}
+
+/// Every quality the recognizer knows about.
+pub const QUALITIES: [Quality; 16] = [
+    Quality { name: "major", harte: "maj", intervals: &[0, 4, 7], vocabulary: Vocabulary::MajMin },
+    Quality { name: "minor", harte: "min", intervals: &[0, 3, 7], vocabulary: Vocabulary::MajMin },
+    Quality { name: "diminished", harte: "dim", intervals: &[0, 3, 6], vocabulary: Vocabulary::Triads },
+    Quality { name: "augmented", harte: "aug", intervals: &[0, 4, 8], vocabulary: Vocabulary::Triads },
+    Quality { name: "sus2", harte: "sus2", intervals: &[0, 2, 7], vocabulary: Vocabulary::Triads },
+    Quality { name: "sus4", harte: "sus4", intervals: &[0, 5, 7], vocabulary: Vocabulary::Triads },
+    Quality { name: "dominant 7th", harte: "7", intervals: &[0, 4, 7, 10], vocabulary: Vocabulary::Sevenths },
+    Quality { name: "major 7th", harte: "maj7", intervals: &[0, 4, 7, 11], vocabulary: Vocabulary::Sevenths },
+    Quality { name: "minor 7th", harte: "min7", intervals: &[0, 3, 7, 10], vocabulary: Vocabulary::Sevenths },
+    Quality { name: "diminished 7th", harte: "dim7", intervals: &[0, 3, 6, 9], vocabulary: Vocabulary::Sevenths },
+    Quality { name: "half-diminished 7th", harte: "hdim7", intervals: &[0, 3, 6, 10], vocabulary: Vocabulary::Sevenths },
+    Quality { name: "6th", harte: "maj6", intervals: &[0, 4, 7, 9], vocabulary: Vocabulary::Full },
+    Quality { name: "minor 6th", harte: "min6", intervals: &[0, 3, 7, 9], vocabulary: Vocabulary::Full },
+    Quality { name: "9th", harte: "9", intervals: &[0, 2, 4, 7, 10], vocabulary: Vocabulary::Full },
+    Quality { name: "add9", harte: "maj(9)", intervals: &[0, 2, 4, 7], vocabulary: Vocabulary::Full },
+    Quality { name: "power chord", harte: "5", intervals: &[0, 7], vocabulary: Vocabulary::Full },
+];
+
+impl Vocabulary {
+    /// Parses `majmin`, `triads`, `sevenths` or `full`.
+    pub fn parse(name: &str) -> Option<Vocabulary> {
+        match name {
+            "majmin" => Some(Vocabulary::MajMin),
+            "triads" => Some(Vocabulary::Triads),
+            "sevenths" => Some(Vocabulary::Sevenths),
+            "full" => Some(Vocabulary::Full),
+            _ => None,
+        }
+    }
+
+    /// The qualities taking part at this level.
+    pub fn qualities(self) -> Vec<&'static Quality> {
+        QUALITIES.iter().filter(|q| q.vocabulary <= self).collect()
+    }
+}
+
+impl Quality {
+    // chord tones are weighted so every template has unit length,
+    // otherwise chords with more notes would always outscore their subsets
+    pub(crate) fn template(&self) -> [f32; 12] {
+        let weight = 1.0 / (self.intervals.len() as f32).sqrt();
+        let mut template = [0.0; 12];
+
+        for &interval in self.intervals {
+            template[interval] = weight;
+        }
+
+        template
+    }
+
+    /// Has a minor third and no major third.
+    pub fn is_minor(&self) -> bool {
+        self.intervals.contains(&3) && !self.intervals.contains(&4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recognizer::{ChordRecognizer, Config};
+    use crate::synth::{ChordSpec, Synth};
+
+    const LEVELS: [Vocabulary; 4] = [Vocabulary::MajMin, Vocabulary::Triads, Vocabulary::Sevenths, Vocabulary::Full];
+
+    #[test]
+    fn levels_add_qualities() {
+        let harte = |vocabulary: Vocabulary| -> Vec<&str> {
+            vocabulary.qualities().iter().map(|quality| quality.harte).collect()
+        };
+        assert_eq!(harte(Vocabulary::MajMin), ["maj", "min"]);
+        assert_eq!(harte(Vocabulary::Triads), ["maj", "min", "dim", "aug", "sus2", "sus4"]);
+        assert_eq!(harte(Vocabulary::Sevenths)[6..], ["7", "maj7", "min7", "dim7", "hdim7"]);
+        assert_eq!(harte(Vocabulary::Full)[11..], ["maj6", "min6", "9", "maj(9)", "5"]);
+    }
+
+    // every quality is recognized from its own level up, and below it comes out as one of the
+    // qualities that level has. Rooted on D2, the lowest D within the chord range
+    #[test]
+    fn recognizes_every_quality_at_its_level() {
+        let synth = Synth::default();
+
+        for vocabulary in LEVELS {
+            let recognizer = ChordRecognizer::new(Config { vocabulary, ..Config::default() });
+            for quality in &QUALITIES {
+                let chord = ChordSpec { root: 2, quality, octave: 2, ..ChordSpec::default() };
+                let result = recognizer.recognize(&synth.render(&chord), synth.sample_rate).unwrap();
+
+                if quality.vocabulary <= vocabulary {
+                    assert_eq!(result.harte(), chord.harte(), "{:?}", vocabulary);
+                } else {
+                    let found = result.quality.map_or(Vocabulary::MajMin, |found| found.vocabulary);
+                    assert!(found <= vocabulary, "{:?}: {} as {}", vocabulary, chord.harte(), result.harte());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,50 @@
+// exit statuses of the command line tool, which scripts tell failures apart by
+
+use std::fs;
+use std::process::{Command, Output};
+
+fn chord(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_chord")).args(args).output().unwrap()
+}
+
+#[test]
+fn input_errors_have_their_own_exit_codes() {
+    let truncated = format!("{}/truncated.wav", env!("CARGO_TARGET_TMPDIR"));
+    let wav = fs::read("samples/amchord.wav").unwrap();
+    fs::write(&truncated, &wav[..wav.len() / 2 + 1]).unwrap();
+
+    let cases: [(&[&str], i32); 5] = [
+        (&["samples/missing.wav"], 2),
+        (&[&truncated], 3),
+        (&["samples/amchord.wav", "--channel", "5"], 5),
+        (&["samples/amchord.wav", "--reference-hz", "10"], 10),
+        (&["samples/amchord.wav", "--segments", "--lab", "samples/missing/out.lab"], 11),
+    ];
+    for (args, code) in cases {
+        let output = chord(args);
+        assert_eq!(output.status.code(), Some(code), "{:?}", args);
+        assert!(String::from_utf8_lossy(&output.stderr).starts_with("Error: "), "{:?}", args);
+    }
+}
+
+#[test]
+fn usage_errors_go_to_stderr() {
+    let cases: [&[&str]; 10] = [
+        &[],
+        &["samples/amchord.wav", "--no-such-option"],
+        &["samples/amchord.wav", "--channel", "left"],
+        &["samples/amchord.wav", "--top"],
+        &["samples/amchord.wav", "--top", "0"],
+        &["samples/amchord.wav", "--no-gate", "--gate-db", "-40"],
+        &["samples/amchord.wav", "--max-flatness", "0.5", "--no-gate"],
+        &["samples/amchord.wav", "--harmonic-wav", "harmonic.wav"],
+        &["evaluate"],
+        &["synth", "out.wav", "--timbre", "kazoo"],
+    ];
+    for args in cases {
+        let output = chord(args);
+        assert_eq!(output.status.code(), Some(64), "{:?}", args);
+        assert!(output.stdout.is_empty(), "{:?}", args);
+        assert!(!output.stderr.is_empty(), "{:?}", args);
+    }
+}